@@ -0,0 +1,62 @@
+//! Compares BVH build methods on a scene.
+//!
+//! ```text
+//! cargo run --release --example bvh_compare [scene.kdl]
+//! ```
+//!
+//! For every method this reports how long loading the scene takes, which
+//! includes building its BVHs, and how fast the world answers the camera's
+//! primary rays.
+
+use std::time::Instant;
+
+use yarr_tracer::{interval::Interval, load_scene_with, sampler::Sampler, Override};
+
+const METHODS: [(&str, &str); 4] = [("median", "1"), ("median", "4"), ("sah", "1"), ("sah", "4")];
+
+fn main() -> miette::Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "final_scene.kdl".to_string());
+
+    println!(
+        "{:<8} {:>9} {:>10} {:>12}",
+        "split", "leaf_size", "load (ms)", "Mrays/s"
+    );
+    for (split, leaf_size) in METHODS {
+        let overrides = [
+            Override::new(&["Bvh", "split"], split.to_string()),
+            Override::new(&["Bvh", "leaf_size"], leaf_size.to_string()),
+        ];
+
+        let start = Instant::now();
+        let scene = load_scene_with(path.clone(), &overrides)?;
+        let load = start.elapsed();
+
+        let camera = &scene.camera;
+        let start = Instant::now();
+        let mut rays = 0;
+        for sample in 0..4 {
+            for j in 0..camera.image_height {
+                for i in 0..camera.image_width {
+                    let mut rng =
+                        Sampler::new(camera.sampler, scene.seed, i, j, sample, camera.samples);
+                    if let Some(r) = camera.get_ray(i, j, &mut rng) {
+                        std::hint::black_box(scene.world.hit(&r, &Interval::from(0.001), &mut rng));
+                        rays += 1;
+                    }
+                }
+            }
+        }
+        let trace = start.elapsed();
+
+        println!(
+            "{:<8} {:>9} {:>10.1} {:>12.2}",
+            split,
+            leaf_size,
+            load.as_secs_f64() * 1000.0,
+            rays as f64 / trace.as_secs_f64() / 1e6
+        );
+    }
+    Ok(())
+}
@@ -5,7 +5,7 @@ use crate::{
 };
 use std::ops;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct AABB {
     pub x: Interval,
     pub y: Interval,
@@ -55,6 +55,19 @@ impl AABB {
         }
     }
 
+    pub fn centroid(&self) -> Point3 {
+        Point3::new(
+            (self.x.min + self.x.max) / 2.0,
+            (self.y.min + self.y.max) / 2.0,
+            (self.z.min + self.z.max) / 2.0,
+        )
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
     pub fn axis(&self, i: usize) -> &Interval {
         if i == 1 {
             &self.y
@@ -1,14 +1,8 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use exmex::Val;
 
-use crate::{
-    color::Color,
-    error::Error,
-    expression::Expression,
-    math::{Vec2, Vec3},
-    rgb,
-};
+use crate::{color::Color, error::Error, expression::Expression, math::Vec3, perlin::Perlin, rgb};
 
 pub trait Background: Send + Sync {
     fn sample_bg(&self, dir: &Vec3) -> Color;
@@ -41,8 +35,8 @@ impl Default for Gradient {
 pub struct BgExpr(Expression);
 
 impl BgExpr {
-    pub fn new(expr: String) -> Result<Self, Error> {
-        Ok(Self(Expression::parse(expr.as_str())?))
+    pub fn new(expr: String, perlin: &Arc<Perlin>) -> Result<Self, Error> {
+        Ok(Self(Expression::parse(expr.as_str(), perlin)?))
     }
 }
 
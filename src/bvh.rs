@@ -1,17 +1,34 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, str::FromStr, time::Instant};
+
+use smallvec::SmallVec;
 
 use crate::{
     aabb::AABB,
+    error::Error,
     group::Group,
     interval::Interval,
-    object::{Hit, Object},
+    math::{Point3, Vec3},
+    object::{uniform_pdf_value, uniform_random, Hit, Object},
     ray::Ray,
+    rng::Rng,
+    stats,
 };
 
+/// A bounding volume hierarchy stored as a flat array of nodes in depth-first
+/// order, so the left child of a node always directly follows it.
 pub struct BVH {
-    left: Box<dyn Object>,
-    right: Box<dyn Object>,
+    nodes: Vec<Node>,
+    objects: Vec<Box<dyn Object>>,
+}
+
+struct Node {
     bbox: AABB,
+    /// The first object of a leaf, or the right child of an interior node.
+    offset: usize,
+    /// The number of objects in a leaf, zero for interior nodes.
+    count: usize,
+    /// The axis interior nodes were split along.
+    axis: usize,
 }
 
 fn box_compare(a: &Box<dyn Object>, b: &Box<dyn Object>, axis: usize) -> Ordering {
@@ -32,42 +49,193 @@ fn box_z_compare(a: &Box<dyn Object>, b: &Box<dyn Object>) -> Ordering {
     box_compare(a, b, 2)
 }
 
-impl BVH {
-    pub fn new(mut objects: Vec<Box<dyn Object>>) -> Box<dyn Object> {
-        let mut bbox = AABB::default();
-        for object in &objects {
-            bbox += object.bbox()
+/// How a BVH node divides its objects between its two children.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SplitMethod {
+    /// Halves the objects sorted along the longest axis of the node.
+    #[default]
+    Median,
+    /// Minimizes the surface area heuristic over binned object centroids.
+    Sah,
+}
+
+impl FromStr for SplitMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "median" => Ok(Self::Median),
+            "sah" => Ok(Self::Sah),
+            _ => Err(Error::UnknownSplitMethod(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BvhOptions {
+    pub split: SplitMethod,
+    /// Nodes with at most this many objects become leaves.
+    pub leaf_size: usize,
+}
+
+impl Default for BvhOptions {
+    fn default() -> Self {
+        Self {
+            split: SplitMethod::Median,
+            leaf_size: 1,
         }
+    }
+}
+
+const SAH_BINS: usize = 16;
 
-        let axis = bbox.longest_axis();
+fn bin_index(c: f64, min: f64, extent: f64) -> usize {
+    (((c - min) / extent * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+}
 
-        let comparator = match axis {
-            0 => box_x_compare,
-            1 => box_y_compare,
-            _ => box_z_compare,
+// Partitions `objects` so that the first `n` go to the left child, choosing
+// the bin boundary with the lowest surface area heuristic, and returns the
+// split axis and `n`. Returns `None` when the centroids can't be told apart.
+fn sah_partition(objects: &mut [Box<dyn Object>]) -> Option<(usize, usize)> {
+    let mut centroids = AABB::default();
+    for object in objects.iter() {
+        let c = object.bbox().centroid();
+        centroids += &AABB {
+            x: Interval::new(c.x(), c.x()),
+            y: Interval::new(c.y(), c.y()),
+            z: Interval::new(c.z(), c.z()),
         };
+    }
 
-        match objects.len() {
-            1 => {
-                let obj = objects.pop().unwrap();
-                obj
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        let min = centroids.axis(axis).min;
+        let extent = centroids.axis(axis).size();
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let mut counts = [0; SAH_BINS];
+        let mut bounds: [AABB; SAH_BINS] = Default::default();
+        for object in objects.iter() {
+            let b = bin_index(object.bbox().centroid()[axis], min, extent);
+            counts[b] += 1;
+            bounds[b] += object.bbox();
+        }
+
+        // Costs of the left sides of every boundary, then sweep the right
+        // sides back over them.
+        let mut left_cost = [0.0; SAH_BINS];
+        let mut area = AABB::default();
+        let mut count = 0;
+        for i in 0..SAH_BINS - 1 {
+            area += &bounds[i];
+            count += counts[i];
+            left_cost[i] = if count > 0 {
+                area.surface_area() * count as f64
+            } else {
+                0.0
+            };
+        }
+        let mut area = AABB::default();
+        let mut count = 0;
+        for i in (1..SAH_BINS).rev() {
+            area += &bounds[i];
+            count += counts[i];
+            if count == 0 || count == objects.len() {
+                continue;
             }
-            2 => {
-                let right = objects.pop().unwrap();
-                let left = objects.pop().unwrap();
-                let bbox = left.bbox() + right.bbox();
-                Box::new(Self { left, right, bbox })
+            let cost = left_cost[i - 1] + area.surface_area() * count as f64;
+            if best.is_none_or(|(c, _, _)| cost < c) {
+                best = Some((cost, axis, i));
             }
-            _ => {
-                objects.sort_by(comparator);
+        }
+    }
+
+    let (_, axis, split) = best?;
+    let min = centroids.axis(axis).min;
+    let extent = centroids.axis(axis).size();
+    let mut n = 0;
+    for i in 0..objects.len() {
+        if bin_index(objects[i].bbox().centroid()[axis], min, extent) < split {
+            objects.swap(i, n);
+            n += 1;
+        }
+    }
+    Some((axis, n))
+}
+
+// Sorts `objects` along the longest axis of their bounds and splits them in
+// half.
+fn median_partition(objects: &mut [Box<dyn Object>], bbox: &AABB) -> (usize, usize) {
+    let axis = bbox.longest_axis();
+    let comparator = match axis {
+        0 => box_x_compare,
+        1 => box_y_compare,
+        _ => box_z_compare,
+    };
+    objects.sort_by(comparator);
+    (axis, objects.len() / 2)
+}
+
+// Appends the subtree over `objects`, which start at `start` in the final
+// object list, to `nodes`.
+fn build_nodes(
+    objects: &mut [Box<dyn Object>],
+    start: usize,
+    options: &BvhOptions,
+    nodes: &mut Vec<Node>,
+) {
+    let mut bbox = AABB::default();
+    for object in objects.iter() {
+        bbox += object.bbox()
+    }
 
-                let right = objects.split_off(objects.len() / 2);
+    if objects.len() <= options.leaf_size.max(1) {
+        nodes.push(Node {
+            bbox,
+            offset: start,
+            count: objects.len(),
+            axis: 0,
+        });
+        return;
+    }
+
+    let split = match options.split {
+        SplitMethod::Sah => sah_partition(objects),
+        SplitMethod::Median => None,
+    };
+    let (axis, mid) = split.unwrap_or_else(|| median_partition(objects, &bbox));
 
-                let left = BVH::new(objects);
-                let right = BVH::new(right);
-                let bbox = left.bbox() + right.bbox();
+    let index = nodes.len();
+    nodes.push(Node {
+        bbox,
+        offset: 0,
+        count: 0,
+        axis,
+    });
+    let (left, right) = objects.split_at_mut(mid);
+    build_nodes(left, start, options, nodes);
+    nodes[index].offset = nodes.len();
+    build_nodes(right, start + mid, options, nodes);
+}
 
-                Box::new(Self { left, right, bbox })
+impl BVH {
+    /// Builds a tree of median splits down to single objects.
+    pub fn new(objects: Vec<Box<dyn Object>>) -> Box<dyn Object> {
+        Self::build(objects, &BvhOptions::default())
+    }
+
+    pub fn build(mut objects: Vec<Box<dyn Object>>, options: &BvhOptions) -> Box<dyn Object> {
+        match objects.len() {
+            0 => Box::new(Group::default()),
+            1 => objects.pop().unwrap(),
+            _ => {
+                let start = Instant::now();
+                let mut nodes = vec![];
+                build_nodes(&mut objects, 0, options, &mut nodes);
+                stats::record(|c| c.bvh_build += start.elapsed());
+                Box::new(Self { nodes, objects })
             }
         }
     }
@@ -78,21 +246,148 @@ impl BVH {
 }
 
 impl Object for BVH {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<Hit> {
-        if !self.bbox.hit(r, *ray_t) {
-            return None;
-        }
+    fn hit(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> Option<Hit> {
+        let dir_is_neg = [
+            r.direction.x() < 0.0,
+            r.direction.y() < 0.0,
+            r.direction.z() < 0.0,
+        ];
+        let mut rec: Option<Hit> = None;
+        let mut closest = ray_t.max;
+        let mut to_visit: SmallVec<[usize; 64]> = SmallVec::new();
+        let mut current = 0;
+        let (mut visited, mut tested) = (0, 0);
 
-        if let Some(hit_left) = self.left.hit(r, ray_t) {
-            self.right
-                .hit(r, &Interval::new(ray_t.min, hit_left.t))
-                .or(Some(hit_left))
-        } else {
-            self.right.hit(r, ray_t)
+        loop {
+            let node = &self.nodes[current];
+            visited += 1;
+            if node.bbox.hit(r, Interval::new(ray_t.min, closest)) {
+                if node.count > 0 {
+                    tested += node.count as u64;
+                    for object in &self.objects[node.offset..node.offset + node.count] {
+                        if let Some(hit) = object.hit(r, &Interval::new(ray_t.min, closest), rng) {
+                            closest = hit.t;
+                            rec = Some(hit);
+                        }
+                    }
+                } else {
+                    // Visit the child nearer to the ray origin first, so hits
+                    // there can cull the other one.
+                    let (near, far) = if dir_is_neg[node.axis] {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    to_visit.push(far);
+                    current = near;
+                    continue;
+                }
+            }
+            match to_visit.pop() {
+                Some(next) => current = next,
+                None => break,
+            }
         }
+
+        stats::record(|c| {
+            c.bvh_nodes += visited;
+            c.primitives += tested;
+        });
+        rec
     }
 
     fn bbox(&self) -> &AABB {
-        &self.bbox
+        &self.nodes[0].bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64, rng: &mut Rng) -> f64 {
+        uniform_pdf_value(&self.objects, origin, direction, time, rng)
+    }
+
+    fn random(&self, origin: &Point3, time: f64, rng: &mut Rng) -> Vec3 {
+        uniform_random(&self.objects, origin, time, rng)
+    }
+
+    fn collect_lights(&self, lights: &mut Vec<Box<dyn Object>>) {
+        for object in &self.objects {
+            object.collect_lights(lights);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use rand::SeedableRng;
+
+    use crate::{
+        color::Color,
+        lambertian::Lambertian,
+        material::Material,
+        math::{Point3, Vec3},
+        rgb,
+        sphere::Sphere,
+    };
+
+    use super::*;
+
+    // Clusters of small spheres next to a few large ones, which median splits
+    // handle poorly.
+    fn spheres(rng: &mut Rng) -> Vec<Box<dyn Object>> {
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::solid(rgb!(0.5)));
+        (0..200)
+            .map(|i| {
+                let radius = if i % 50 == 0 { 5.0 } else { 0.2 };
+                let center = Vec3::random_in_range(rng, -10.0, 10.0);
+                Box::new(Sphere::stationary(center, radius, &mat)) as Box<dyn Object>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_builders_match_brute_force() {
+        let mut rng = Rng::seed_from_u64(0);
+        let group = Group::new(spheres(&mut Rng::seed_from_u64(1)));
+        let trees = [
+            (SplitMethod::Median, 1),
+            (SplitMethod::Median, 4),
+            (SplitMethod::Sah, 1),
+            (SplitMethod::Sah, 4),
+        ]
+        .map(|(split, leaf_size)| {
+            BVH::build(
+                spheres(&mut Rng::seed_from_u64(1)),
+                &BvhOptions { split, leaf_size },
+            )
+        });
+
+        for _ in 0..500 {
+            let r = Ray::new(
+                Point3::random_in_range(&mut rng, -20.0, 20.0),
+                Vec3::random_unit(&mut rng),
+                0.0,
+            );
+            let expected = group.hit(&r, &Interval::from(0.001), &mut rng).map(|h| h.t);
+            for tree in &trees {
+                let t = tree.hit(&r, &Interval::from(0.001), &mut rng).map(|h| h.t);
+                assert_eq!(t, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let tree = BVH::build(vec![], &BvhOptions::default());
+        let r = Ray::new(Point3::default(), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(tree
+            .hit(&r, &Interval::from(0.001), &mut Rng::seed_from_u64(0))
+            .is_none());
+    }
+
+    #[test]
+    fn test_parse_split() {
+        assert_eq!("SAH".parse::<SplitMethod>().unwrap(), SplitMethod::Sah);
+        assert!("middle".parse::<SplitMethod>().is_err());
     }
 }
@@ -1,10 +1,68 @@
-use rand::random;
-
 use crate::ray::Ray;
-use crate::vec3;
+use crate::rng::Rng;
+use crate::sampler::SamplerKind;
+use crate::shutter::Shutter;
+use crate::util::sphere_direction;
+use crate::vec2;
+
+use crate::math::{Point3, Vec2, Vec3};
+
+/// How a fisheye lens maps the angle from the view direction to the distance
+/// from the image center.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FisheyeMapping {
+    /// The distance grows with the angle.
+    #[default]
+    Equidistant,
+    /// Equal solid angles cover equal areas of the image.
+    Equisolid,
+}
 
-use crate::math::{Point3, Vec3};
+/// How rays leave the camera.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Projection {
+    /// Rays spread out from the lens to cover the vertical field of view.
+    #[default]
+    Perspective,
+    /// Parallel rays along the view direction, from a `width` by `height`
+    /// rectangle centered on the camera. Defocus doesn't apply.
+    Orthographic { width: f64, height: f64 },
+    /// The whole sphere of directions, laid out by latitude and longitude
+    /// like [`sphere_uv`](crate::util::sphere_uv) in the camera frame, so
+    /// the image can serve as an image background.
+    Equirectangular,
+    /// A circular image, as wide as the shorter side, covering `fov` degrees
+    /// around the view direction. Pixels outside the circle stay black.
+    Fisheye { mapping: FisheyeMapping, fov: f64 },
+    /// The six faces of a cube map side by side in the order +x, -x, +y, -y,
+    /// +z, -z of the camera frame, each a square 90 degree view from inside
+    /// taking up a sixth of the image width. The side faces keep +y up, the
+    /// +y face has -z at the bottom and the -y face has it at the top, so
+    /// they fold around the forward -z face.
+    CubeMap,
+}
 
+/// How the views of the two eyes are packed into one image, the left eye
+/// first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StereoLayout {
+    #[default]
+    SideBySide,
+    TopBottom,
+}
+
+/// Renders the scene from two eyes `interocular` apart, centered on the
+/// camera. Their views line up at `convergence` from the camera, which is
+/// infinite for parallel eyes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stereo {
+    pub layout: StereoLayout,
+    pub interocular: f64,
+    pub convergence: f64,
+}
+
+/// A camera with a thin-lens perspective, an orthographic or one of the
+/// panoramic projections, along with the image and sampling settings.
 pub struct Camera {
     pub image_width: usize,
     pub image_height: usize,
@@ -12,8 +70,18 @@ pub struct Camera {
     pub samples_scale: f64,
     pub max_depth: u32,
     pub defocus_angle: f64,
+    pub sampler: SamplerKind,
+    pub shutter: Shutter,
 
+    projection: Projection,
+    stereo: Option<Stereo>,
+    vfov: f64,
+    focus_dist: f64,
     center: Point3,
+    // Unit basis vectors, pointing right, up and backwards.
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
     pixel00_loc: Point3,
@@ -22,6 +90,8 @@ pub struct Camera {
 }
 
 impl Camera {
+    /// A perspective camera, see [`with_projection`](Self::with_projection)
+    /// for the others.
     pub fn new(
         image_width: usize,
         image_height: usize,
@@ -34,73 +104,432 @@ impl Camera {
         samples: u32,
         max_depth: u32,
     ) -> Self {
-        let aspect_ratio = (image_width as f64) / (image_height as f64);
-
-        // Camera
-
-        let center = lookfrom;
-
-        let theta = vfov.to_radians();
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * focus_dist;
-        let viewport_width = viewport_height * aspect_ratio;
-
         // Calculate the u,v,w unit basis vectors for the camera coordinate frame.
         let w = (lookfrom - lookat).unit();
         let u = vup.cross(&w).unit();
         let v = w.cross(&u);
 
-        // Calculate the vectors across the horizontal and down the vertical viewport edges.
-        let viewport_u = viewport_width * u;
-        let viewport_v = viewport_height * -v;
-
-        // Calculate the horizontal and vertical delta vectors from pixel to pixel.
-        let pixel_delta_u = viewport_u / (image_width as f64);
-        let pixel_delta_v = viewport_v / (image_height as f64);
-
-        // Calculate the location of the upper left pixel.
-        let viewport_upper_left = center - (focus_dist * w) - viewport_u / 2.0 - viewport_v / 2.0;
-        let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
-
         // Calculate the camera defocus disk basis vectors.
         let defocus_radius = focus_dist * (defocus_angle / 2.0).to_radians().tan();
         let defocus_disk_u = u * defocus_radius;
         let defocus_disk_v = v * defocus_radius;
 
-        Self {
+        let mut camera = Self {
             image_width,
             image_height,
             samples,
             samples_scale: 1.0 / (samples as f64),
             max_depth,
             defocus_angle,
-            center,
-            pixel_delta_u,
-            pixel_delta_v,
-            pixel00_loc,
+            sampler: SamplerKind::default(),
+            shutter: Shutter::default(),
+            projection: Projection::default(),
+            stereo: None,
+            vfov,
+            focus_dist,
+            center: lookfrom,
+            u,
+            v,
+            w,
+            pixel_delta_u: Vec3::default(),
+            pixel_delta_v: Vec3::default(),
+            pixel00_loc: Point3::default(),
             defocus_disk_u,
             defocus_disk_v,
-        }
+        };
+        camera.update_viewport();
+        camera
     }
 
-    pub fn get_ray(&self, i: usize, j: usize) -> Ray {
-        let offset = vec3!(random::<f64>() - 0.5, random::<f64>() - 0.5, 0.0);
-        let pixel_sample = self.pixel00_loc
-            + (((i as f64) + offset.x()) * self.pixel_delta_u)
-            + (((j as f64) + offset.x()) * self.pixel_delta_v);
+    /// Switches to `projection`, keeping the position and orientation.
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self.update_viewport();
+        self
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    /// Renders both eyes of `stereo` into the image, or a single view for
+    /// `None`.
+    pub fn with_stereo(mut self, stereo: Option<Stereo>) -> Self {
+        self.stereo = stereo;
+        self.update_viewport();
+        self
+    }
+
+    pub fn stereo(&self) -> Option<Stereo> {
+        self.stereo
+    }
+
+    // Places the grid of pixel centers of one eye: on the focus plane for
+    // perspective, through the camera center for orthographic.
+    fn update_viewport(&mut self) {
+        let (width, height) = self.eye_size();
+        let (viewport_width, viewport_height, distance) = match self.projection {
+            Projection::Perspective => {
+                let aspect_ratio = width / height;
+                let h = (self.vfov.to_radians() / 2.0).tan();
+                let viewport_height = 2.0 * h * self.focus_dist;
+                (
+                    viewport_height * aspect_ratio,
+                    viewport_height,
+                    self.focus_dist,
+                )
+            }
+            Projection::Orthographic { width, height } => (width, height, 0.0),
+            // Panoramic rays don't go through a grid.
+            _ => return,
+        };
+
+        // Calculate the vectors across the horizontal and down the vertical viewport edges.
+        let viewport_u = viewport_width * self.u;
+        let viewport_v = viewport_height * -self.v;
+
+        // Calculate the horizontal and vertical delta vectors from pixel to pixel.
+        self.pixel_delta_u = viewport_u / width;
+        self.pixel_delta_v = viewport_v / height;
+
+        // Calculate the location of the upper left pixel.
+        let viewport_upper_left =
+            self.center - (distance * self.w) - viewport_u / 2.0 - viewport_v / 2.0;
+        self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+    }
+
+    /// Returns a ray through a random point of pixel `(i, j)`, taking the
+    /// pixel offset, the lens position and the time within the shutter
+    /// interval from the camera dimensions of `rng`. There is none where the
+    /// projection doesn't cover the image.
+    pub fn get_ray(&self, i: usize, j: usize, rng: &mut Rng) -> Option<Ray> {
+        let (dx, dy) = rng.get_2d();
+        self.get_ray_at(i as f64 + dx, j as f64 + dy, rng)
+    }
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
+    /// Returns a ray through the film position `(x, y)`, in pixels from the
+    /// top left corner of the image.
+    pub fn get_ray_at(&self, x: f64, y: f64, rng: &mut Rng) -> Option<Ray> {
+        let (x, y, eye) = self.eye_at(x, y);
+        let (width, height) = self.eye_size();
+        let convergence = self.stereo.map_or(f64::INFINITY, |s| s.convergence);
+
+        // Always drawn, so the time dimension stays in place.
+        let lens = Vec3::random_in_unit_disk(rng);
+        let time = self.shutter.time(rng.get_1d(), y / height);
+        let local = match self.projection {
+            Projection::Perspective | Projection::Orthographic { .. } => {
+                let pixel_sample = self.pixel00_loc
+                    + ((x - 0.5) * self.pixel_delta_u)
+                    + ((y - 0.5) * self.pixel_delta_v);
+                let eye_center = self.center + eye * self.u;
+                let (ray_origin, ray_direction) = match self.projection {
+                    Projection::Orthographic { .. } => (pixel_sample + eye * self.u, -self.w),
+                    _ => {
+                        let ray_origin = if self.defocus_angle <= 0.0 {
+                            eye_center
+                        } else {
+                            eye_center
+                                + (lens.x() * self.defocus_disk_u)
+                                + (lens.y() * self.defocus_disk_v)
+                        };
+                        // The eyes look the same way, with their views
+                        // shifted to line up at the convergence distance.
+                        let target =
+                            pixel_sample + (1.0 - self.focus_dist / convergence) * eye * self.u;
+                        (ray_origin, target - ray_origin)
+                    }
+                };
+                return Some(Ray::new(ray_origin, ray_direction, time));
+            }
+            Projection::Equirectangular => {
+                let uv = vec2!(x / width, 1.0 - y / height);
+                sphere_direction(&uv)
+            }
+            Projection::Fisheye { mapping, fov } => {
+                let radius = width.min(height) / 2.0;
+                let (px, py) = ((x - width / 2.0) / radius, (height / 2.0 - y) / radius);
+                let r = (px * px + py * py).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let half_fov = fov.to_radians() / 2.0;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => 2.0 * (r * (half_fov / 2.0).sin()).asin(),
+                };
+                let phi = py.atan2(px);
+                Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    -theta.cos(),
+                )
+            }
+            Projection::CubeMap => {
+                let size = width / 6.0;
+                let face = ((x / size) as usize).min(5);
+                // Face coordinates in [-1, 1], right and down.
+                let s = 2.0 * (x - face as f64 * size) / size - 1.0;
+                let t = 2.0 * y / height - 1.0;
+                match face {
+                    0 => Vec3::new(1.0, -t, s),
+                    1 => Vec3::new(-1.0, -t, -s),
+                    2 => Vec3::new(s, 1.0, -t),
+                    3 => Vec3::new(s, -1.0, t),
+                    4 => Vec3::new(-s, -t, 1.0),
+                    _ => Vec3::new(s, -t, -1.0),
+                }
+            }
+        };
+
+        // The eye sits to the side of the panoramic direction. For
+        // equirectangular images this is omnidirectional stereo: the eyes
+        // turn with every column, and come together towards the poles so
+        // the views still meet there.
+        let eye_local = match self.projection {
+            Projection::Equirectangular => eye * Vec3::new(-local.z(), 0.0, local.x()),
+            _ => Vec3::new(eye, 0.0, 0.0),
+        };
+        let local = if convergence.is_finite() {
+            convergence * local.unit() - eye_local
         } else {
-            self.defocus_disk_sample()
+            local
+        };
+        Some(Ray::new(
+            self.center + self.to_world(&eye_local),
+            self.to_world(&local),
+            time,
+        ))
+    }
+
+    // The size of the view of one eye, or the whole image without stereo.
+    fn eye_size(&self) -> (f64, f64) {
+        let (width, height) = (self.image_width as f64, self.image_height as f64);
+        match self.stereo {
+            None => (width, height),
+            Some(Stereo {
+                layout: StereoLayout::SideBySide,
+                ..
+            }) => (width / 2.0, height),
+            Some(Stereo {
+                layout: StereoLayout::TopBottom,
+                ..
+            }) => (width, height / 2.0),
+        }
+    }
+
+    // The film position within the view of its eye, and how far that eye is
+    // to the right of the center.
+    fn eye_at(&self, x: f64, y: f64) -> (f64, f64, f64) {
+        let Some(stereo) = self.stereo else {
+            return (x, y, 0.0);
         };
-        let ray_direction = pixel_sample - ray_origin;
+        let (width, height) = self.eye_size();
+        let half = stereo.interocular / 2.0;
+        match stereo.layout {
+            StereoLayout::SideBySide if x < width => (x, y, -half),
+            StereoLayout::SideBySide => (x - width, y, half),
+            StereoLayout::TopBottom if y < height => (x, y, -half),
+            StereoLayout::TopBottom => (x, y - height, half),
+        }
+    }
+
+    // From the camera frame, where the camera looks down -z with +y up, to
+    // the world.
+    fn to_world(&self, local: &Vec3) -> Vec3 {
+        local.x() * self.u + local.y() * self.v + local.z() * self.w
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use std::f64::consts::FRAC_PI_4;
+
+    use crate::{assert_in_delta, point, vec3};
+
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::new(
+            40,
+            20,
+            90.0,
+            point!(0.0, 0.0, 5.0),
+            point!(0.0, 0.0, 0.0),
+            vec3!(0.0, 1.0, 0.0),
+            0.0,
+            1.0,
+            1,
+            1,
+        )
+    }
+
+    #[test]
+    fn test_perspective() {
+        let camera = camera();
+        let mut rng = Rng::seed_from_u64(0);
+        let r = camera.get_ray_at(20.0, 10.0, &mut rng).unwrap();
+        assert_eq!(r.origin, point!(0.0, 0.0, 5.0));
+        assert_eq!(r.direction, vec3!(0.0, 0.0, -1.0));
 
-        Ray::new(ray_origin, ray_direction, random())
+        // The top right corner is 45 degrees up and 63 to the side.
+        let r = camera.get_ray_at(40.0, 0.0, &mut rng).unwrap();
+        assert_eq!(r.direction, vec3!(2.0, 1.0, -1.0));
     }
 
-    fn defocus_disk_sample(&self) -> Vec3 {
-        let p = Vec3::random_in_unit_disk();
-        self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v)
+    #[test]
+    fn test_orthographic() {
+        let camera = camera().with_projection(Projection::Orthographic {
+            width: 8.0,
+            height: 4.0,
+        });
+        let mut rng = Rng::seed_from_u64(0);
+        let r = camera.get_ray_at(0.0, 0.0, &mut rng).unwrap();
+        assert_eq!(r.origin, point!(-4.0, 2.0, 5.0));
+        assert_eq!(r.direction, vec3!(0.0, 0.0, -1.0));
+
+        let r = camera.get_ray_at(30.0, 15.0, &mut rng).unwrap();
+        assert_eq!(r.origin, point!(2.0, -1.0, 5.0));
+        assert_eq!(r.direction, vec3!(0.0, 0.0, -1.0));
+    }
+
+    fn stereo(layout: StereoLayout, convergence: f64) -> Option<Stereo> {
+        Some(Stereo {
+            layout,
+            interocular: 1.0,
+            convergence,
+        })
+    }
+
+    #[test]
+    fn test_stereo() {
+        let mut rng = Rng::seed_from_u64(0);
+        let camera = camera().with_stereo(stereo(StereoLayout::SideBySide, f64::INFINITY));
+        let r = camera.get_ray_at(10.0, 10.0, &mut rng).unwrap();
+        assert_eq!(r.origin, point!(-0.5, 0.0, 5.0));
+        assert_eq!(r.direction, vec3!(0.0, 0.0, -1.0));
+        let r = camera.get_ray_at(30.0, 10.0, &mut rng).unwrap();
+        assert_eq!(r.origin, point!(0.5, 0.0, 5.0));
+        assert_eq!(r.direction, vec3!(0.0, 0.0, -1.0));
+        // Each eye gets a square half of the image.
+        let r = camera.get_ray_at(20.0, 0.0, &mut rng).unwrap();
+        assert_eq!(r.direction, vec3!(-1.0, 1.0, -1.0));
+
+        // Converging at the focus distance.
+        let camera = camera.with_stereo(stereo(StereoLayout::TopBottom, 1.0));
+        let r = camera.get_ray_at(20.0, 5.0, &mut rng).unwrap();
+        assert_eq!(r.origin, point!(-0.5, 0.0, 5.0));
+        assert_eq!(r.direction, vec3!(0.5, 0.0, -1.0));
+        let r = camera.get_ray_at(20.0, 15.0, &mut rng).unwrap();
+        assert_eq!(r.origin, point!(0.5, 0.0, 5.0));
+        assert_eq!(r.direction, vec3!(-0.5, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_omnidirectional_stereo() {
+        let mut rng = Rng::seed_from_u64(0);
+        let camera = camera()
+            .with_projection(Projection::Equirectangular)
+            .with_stereo(stereo(StereoLayout::SideBySide, f64::INFINITY));
+        // Looking forward, the left eye is to the left.
+        let r = camera.get_ray_at(15.0, 10.0, &mut rng).unwrap();
+        assert_in_delta!((r.origin - point!(-0.5, 0.0, 5.0)).length(), 0.0);
+        assert_in_delta!((r.direction - vec3!(0.0, 0.0, -1.0)).length(), 0.0);
+        // Looking along +x, it is towards -z.
+        let r = camera.get_ray_at(10.0, 10.0, &mut rng).unwrap();
+        assert_in_delta!((r.origin - point!(0.0, 0.0, 4.5)).length(), 0.0);
+        let r = camera.get_ray_at(30.0, 10.0, &mut rng).unwrap();
+        assert_in_delta!((r.origin - point!(0.0, 0.0, 5.5)).length(), 0.0);
+        // Straight up, the eyes meet.
+        let r = camera.get_ray_at(13.0, 0.0, &mut rng).unwrap();
+        assert_in_delta!((r.origin - point!(0.0, 0.0, 5.0)).length(), 0.0);
+
+        // Converging two units away.
+        let camera = camera.with_stereo(stereo(StereoLayout::SideBySide, 2.0));
+        let r = camera.get_ray_at(15.0, 10.0, &mut rng).unwrap();
+        assert_in_delta!(
+            (r.origin + r.direction - point!(0.0, 0.0, 3.0)).length(),
+            0.0
+        );
+    }
+
+    fn assert_direction(camera: &Camera, x: f64, y: f64, expected: Vec3) {
+        let mut rng = Rng::seed_from_u64(0);
+        let r = camera.get_ray_at(x, y, &mut rng).unwrap();
+        assert_eq!(r.origin, point!(0.0, 0.0, 5.0));
+        assert_in_delta!((r.direction.unit() - expected).length(), 0.0);
+    }
+
+    #[test]
+    fn test_equirectangular() {
+        let camera = camera().with_projection(Projection::Equirectangular);
+        // The same directions as sphere_uv.
+        assert_direction(&camera, 20.0, 10.0, vec3!(1.0, 0.0, 0.0));
+        assert_direction(&camera, 10.0, 10.0, vec3!(0.0, 0.0, 1.0));
+        assert_direction(&camera, 30.0, 10.0, vec3!(0.0, 0.0, -1.0));
+        assert_direction(&camera, 7.0, 0.0, vec3!(0.0, 1.0, 0.0));
+        assert_direction(&camera, 7.0, 20.0, vec3!(0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn test_fisheye() {
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let camera = camera().with_projection(Projection::Fisheye {
+                mapping,
+                fov: 180.0,
+            });
+            assert_direction(&camera, 20.0, 10.0, vec3!(0.0, 0.0, -1.0));
+            assert_direction(&camera, 30.0, 10.0, vec3!(1.0, 0.0, 0.0));
+            assert_direction(&camera, 20.0, 0.0, vec3!(0.0, 1.0, 0.0));
+            let mut rng = Rng::seed_from_u64(0);
+            assert!(camera.get_ray_at(0.0, 0.0, &mut rng).is_none());
+        }
+
+        // Halfway out is 45 degrees for equidistant, and less for equisolid.
+        let camera = camera().with_projection(Projection::Fisheye {
+            mapping: FisheyeMapping::Equidistant,
+            fov: 180.0,
+        });
+        assert_direction(&camera, 25.0, 10.0, vec3!(1.0, 0.0, -1.0).unit());
+        let camera = camera.with_projection(Projection::Fisheye {
+            mapping: FisheyeMapping::Equisolid,
+            fov: 180.0,
+        });
+        let theta = 2.0 * (0.5 * FRAC_PI_4.sin()).asin();
+        assert_direction(&camera, 25.0, 10.0, vec3!(theta.sin(), 0.0, -theta.cos()));
+    }
+
+    #[test]
+    fn test_cube_map() {
+        let camera = Camera::new(
+            60,
+            10,
+            90.0,
+            point!(0.0, 0.0, 5.0),
+            point!(0.0, 0.0, 0.0),
+            vec3!(0.0, 1.0, 0.0),
+            0.0,
+            1.0,
+            1,
+            1,
+        )
+        .with_projection(Projection::CubeMap);
+        let faces = [
+            vec3!(1.0, 0.0, 0.0),
+            vec3!(-1.0, 0.0, 0.0),
+            vec3!(0.0, 1.0, 0.0),
+            vec3!(0.0, -1.0, 0.0),
+            vec3!(0.0, 0.0, 1.0),
+            vec3!(0.0, 0.0, -1.0),
+        ];
+        for (face, expected) in faces.into_iter().enumerate() {
+            assert_direction(&camera, face as f64 * 10.0 + 5.0, 5.0, expected);
+        }
+        // The top left corner of the forward face, and the edge of the +y
+        // face next to it.
+        assert_direction(&camera, 50.0, 0.0, vec3!(-1.0, 1.0, -1.0).unit());
+        assert_direction(&camera, 25.0, 10.0, vec3!(0.0, 1.0, -1.0).unit());
     }
 }
@@ -0,0 +1,235 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{color::Color, error::Error, progressive::Accumulation, rgb, scene::Scene};
+
+const MAGIC: &[u8; 8] = b"YARRCKPT";
+const VERSION: u32 = 3;
+// The magic, version, scene hash, seed, width and height.
+const HEADER_BYTES: u64 = 8 + 4 + 8 + 8 + 4 + 4;
+// The color, weight and luminance sums and the sample count.
+const PIXEL_BYTES: u64 = 6 * 8 + 4;
+
+/// A partly finished render saved to disk, to be continued later.
+///
+/// Every sample seeds its own generator from the scene seed, the pixel and
+/// the sample's index, so the seed and the per-pixel sample counts are all
+/// the random state needed to continue exactly where the render stopped.
+pub struct Checkpoint {
+    /// [`Scene::hash`] of the scene being rendered.
+    pub scene_hash: u64,
+    pub seed: u64,
+    pub acc: Accumulation,
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32, Error> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64, Error> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64(r: &mut impl Read) -> Result<f64, Error> {
+    Ok(f64::from_bits(read_u64(r)?))
+}
+
+impl Checkpoint {
+    /// Writes a checkpoint of `acc`, rendered from `scene`, to `path`. An
+    /// existing file is replaced only once the new one is complete.
+    pub fn save(scene: &Scene, acc: &Accumulation, path: &Path) -> Result<(), Error> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&scene.hash.to_le_bytes())?;
+        w.write_all(&scene.seed.to_le_bytes())?;
+        w.write_all(&(acc.sums.width as u32).to_le_bytes())?;
+        w.write_all(&(acc.sums.height as u32).to_le_bytes())?;
+        for j in 0..acc.sums.height {
+            for i in 0..acc.sums.width {
+                let c = acc.sums.get(i, j);
+                for x in [c.r(), c.g(), c.b()] {
+                    w.write_all(&x.to_bits().to_le_bytes())?;
+                }
+                let p = j * acc.sums.width + i;
+                for x in [acc.weights[p], acc.luminance[p], acc.luminance_sq[p]] {
+                    w.write_all(&x.to_bits().to_le_bytes())?;
+                }
+                w.write_all(&acc.samples[p].to_le_bytes())?;
+            }
+        }
+        w.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut r = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::Checkpoint("not a checkpoint file".into()));
+        }
+        let version = read_u32(&mut r)?;
+        if version != VERSION {
+            return Err(Error::Checkpoint(format!(
+                "unsupported version {}",
+                version
+            )));
+        }
+
+        let scene_hash = read_u64(&mut r)?;
+        let seed = read_u64(&mut r)?;
+        let width = read_u32(&mut r)? as usize;
+        let height = read_u32(&mut r)? as usize;
+        // Checked before allocating, so a damaged file can't ask for more
+        // pixels than it holds.
+        let pixels = (width as u64).checked_mul(height as u64);
+        if pixels.and_then(|n| n.checked_mul(PIXEL_BYTES)) != Some(len - HEADER_BYTES) {
+            return Err(Error::Checkpoint(format!(
+                "the file is damaged, it doesn't hold {}x{} pixels",
+                width, height
+            )));
+        }
+
+        let mut acc = Accumulation::new(width, height);
+        for j in 0..height {
+            for i in 0..width {
+                let p = j * width + i;
+                let c = rgb!(read_f64(&mut r)?, read_f64(&mut r)?, read_f64(&mut r)?);
+                acc.sums.set(i, j, c);
+                acc.weights[p] = read_f64(&mut r)?;
+                acc.luminance[p] = read_f64(&mut r)?;
+                acc.luminance_sq[p] = read_f64(&mut r)?;
+                acc.samples[p] = read_u32(&mut r)?;
+            }
+        }
+
+        Ok(Self {
+            scene_hash,
+            seed,
+            acc,
+        })
+    }
+
+    /// Checks that the checkpoint was saved while rendering `scene`.
+    pub fn validate(&self, scene: &Scene) -> Result<(), Error> {
+        let camera = &scene.camera;
+        if self.scene_hash != scene.hash {
+            Err(Error::Checkpoint(
+                "it was saved for a different scene".into(),
+            ))
+        } else if self.seed != scene.seed {
+            Err(Error::Checkpoint(format!(
+                "it was saved with seed {}, not {}",
+                self.seed, scene.seed
+            )))
+        } else if (self.acc.sums.width, self.acc.sums.height)
+            != (camera.image_width, camera.image_height)
+        {
+            Err(Error::Checkpoint(format!(
+                "it was saved at {}x{}, not {}x{}",
+                self.acc.sums.width, self.acc.sums.height, camera.image_width, camera.image_height
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_data;
+
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // A file name no other test or test run uses.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "yarr_checkpoint_{}_{}.ckpt",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn scene() -> Scene {
+        let mut scene = test_data::scene(3, 2, 10);
+        scene.hash = 0xfeed;
+        scene.seed = 7;
+        scene
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let scene = scene();
+        let mut acc = Accumulation::new(3, 2);
+        acc.sums.set(2, 1, rgb!(0.1, 0.2, 1e30));
+        acc.weights = vec![3.0, 0.0, 0.0, 0.0, 0.5, 4.5];
+        acc.luminance = vec![1.0, 0.0, 0.0, 0.0, 0.0, 2.0];
+        acc.luminance_sq = vec![3.0, 0.0, 0.0, 0.0, 0.0, 0.5];
+        acc.samples = vec![3, 0, 0, 0, 0, 5];
+
+        let path = temp_path("round_trip");
+        Checkpoint::save(&scene, &acc, &path).unwrap();
+        let read = Checkpoint::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        read.validate(&scene).unwrap();
+        assert_eq!(read.acc.samples, vec![3, 0, 0, 0, 0, 5]);
+        assert_eq!(read.acc.sums.get(2, 1), rgb!(0.1, 0.2, 1e30));
+        assert_eq!(read.acc.weights, acc.weights);
+        assert_eq!(read.acc.luminance, acc.luminance);
+        assert_eq!(read.acc.luminance_sq, acc.luminance_sq);
+    }
+
+    #[test]
+    fn test_damaged() {
+        let scene = scene();
+        let path = temp_path("damaged");
+        Checkpoint::save(&scene, &Accumulation::new(3, 2), &path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+
+        // Truncated.
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(Checkpoint::read(&path), Err(Error::Checkpoint(_))));
+        // An enormous size.
+        bytes[28..36].copy_from_slice(&[0xff; 8]);
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(Checkpoint::read(&path), Err(Error::Checkpoint(_))));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut scene = scene();
+        let checkpoint = Checkpoint {
+            scene_hash: scene.hash,
+            seed: scene.seed,
+            acc: Accumulation::new(3, 2),
+        };
+        scene.seed = 8;
+        assert!(checkpoint.validate(&scene).is_err());
+        scene.seed = 7;
+        scene.hash = 1;
+        assert!(checkpoint.validate(&scene).is_err());
+        scene.hash = 0xfeed;
+        scene.camera.image_width = 4;
+        assert!(checkpoint.validate(&scene).is_err());
+    }
+}
@@ -0,0 +1,421 @@
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+use yarr_tracer::{
+    AdaptiveOptions, OutputFormat, Override, ProgressiveOptions, SamplerKind, TileOrder,
+};
+
+pub const USAGE: &str = "Usage: yarr [options] <path_to_file.kdl>
+
+Options:
+  -o, --output <file>      Output image (png, jpg, tga, hdr, exr, ppm) [default: output.png]
+      --format <format>    Output format, overriding the file extension (e.g. ppm-ascii)
+  -t, --threads <n>        Number of render threads [default: number of CPUs]
+      --single-threaded    Render on the main thread only
+      --tile-size <n>      Width and height of render tiles in pixels [default: 16]
+      --tile-order <order> Tile order: scanline, spiral or hilbert [default: spiral]
+      --progressive        Render in passes over the whole image
+      --pass-samples <n>   Samples per pixel added by each progressive pass [default: 1, adaptive: 8]
+      --snapshot-passes <n>
+                           Write the output image every n progressive passes
+      --snapshot-seconds <s>
+                           Write the output image every s seconds while rendering
+      --time-limit <s>     Stop after the first progressive pass ending past s seconds
+      --checkpoint <file>  Save progress to a checkpoint with every snapshot [default: every 60s]
+      --resume <file>      Continue the render saved in a checkpoint, updating it as it goes
+      --adaptive           Spend the samples on the noisiest pixels, with camera.samples as the average
+      --adaptive-threshold <x>
+                           Relative error below which a pixel is done [default: 0.01]
+      --min-samples <n>    Samples every pixel gets with --adaptive [default: 16]
+      --max-samples <n>    Most samples a pixel gets with --adaptive [default: 4 * camera.samples]
+      --sample-heatmap <file>
+                           Write an image of the samples used per pixel
+      --samples <n>        Override camera.samples
+      --max-depth <n>      Override camera.max_depth
+      --width <n>          Override camera.image_width
+      --height <n>         Override camera.image_height
+      --seed <n>           Override the scene seed
+      --sampler <kind>     Override camera.sampler: independent, stratified, halton or sobol
+      --set <key=value>    Override a scene value, e.g. --set camera.vfov=30
+      --stats-json <file>  Write render statistics as JSON
+  -q, --quiet              Don't print progress or the render report
+  -h, --help               Print this message";
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum CliError {
+    #[error("Missing scene file")]
+    MissingScene,
+    #[error("Missing value for {0}")]
+    MissingValue(String),
+    #[error("Invalid value {1:?} for {0}")]
+    InvalidValue(String, String),
+    #[error("Unknown option {0}")]
+    UnknownOption(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub scene: String,
+    pub output: PathBuf,
+    pub format: Option<OutputFormat>,
+    pub threads: Option<usize>,
+    pub single_threaded: bool,
+    pub tile_size: Option<usize>,
+    pub tile_order: Option<TileOrder>,
+    pub progressive: bool,
+    pub pass_samples: Option<u32>,
+    pub snapshot_passes: Option<u32>,
+    pub snapshot_seconds: Option<Duration>,
+    pub time_limit: Option<Duration>,
+    pub checkpoint: Option<PathBuf>,
+    pub resume: Option<PathBuf>,
+    pub adaptive: bool,
+    pub adaptive_threshold: Option<f64>,
+    pub min_samples: Option<u32>,
+    pub max_samples: Option<u32>,
+    pub sample_heatmap: Option<PathBuf>,
+    pub overrides: Vec<Override>,
+    pub stats_json: Option<PathBuf>,
+    pub quiet: bool,
+    pub help: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            scene: String::new(),
+            output: PathBuf::from("output.png"),
+            format: None,
+            threads: None,
+            single_threaded: false,
+            tile_size: None,
+            tile_order: None,
+            progressive: false,
+            pass_samples: None,
+            snapshot_passes: None,
+            snapshot_seconds: None,
+            time_limit: None,
+            checkpoint: None,
+            resume: None,
+            adaptive: false,
+            adaptive_threshold: None,
+            min_samples: None,
+            max_samples: None,
+            sample_heatmap: None,
+            overrides: Vec::new(),
+            stats_json: None,
+            quiet: false,
+            help: false,
+        }
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: &str) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError::InvalidValue(flag.to_string(), value.to_string()))
+}
+
+// How often checkpoints are saved when no snapshot schedule is given.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+fn parse_seconds(flag: &str, value: &str) -> Result<Duration, CliError> {
+    Duration::try_from_secs_f64(parse_value(flag, value)?)
+        .map_err(|_| CliError::InvalidValue(flag.to_string(), value.to_string()))
+}
+
+impl Args {
+    pub fn parse<I>(args: I) -> Result<Self, CliError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut parsed = Args::default();
+        let mut scene = None;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| CliError::MissingValue(flag.clone()))
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => parsed.help = true,
+                "-o" | "--output" => parsed.output = PathBuf::from(value()?),
+                "--format" => parsed.format = Some(parse_value(&flag, &value()?)?),
+                "-t" | "--threads" => parsed.threads = Some(parse_value(&flag, &value()?)?),
+                "--single-threaded" => parsed.single_threaded = true,
+                "-q" | "--quiet" => parsed.quiet = true,
+                "--tile-size" => parsed.tile_size = Some(parse_value(&flag, &value()?)?),
+                "--tile-order" => parsed.tile_order = Some(parse_value(&flag, &value()?)?),
+                "--progressive" => parsed.progressive = true,
+                "--pass-samples" => parsed.pass_samples = Some(parse_value(&flag, &value()?)?),
+                "--snapshot-passes" => {
+                    parsed.snapshot_passes = Some(parse_value(&flag, &value()?)?)
+                }
+                "--snapshot-seconds" => {
+                    parsed.snapshot_seconds = Some(parse_seconds(&flag, &value()?)?)
+                }
+                "--time-limit" => parsed.time_limit = Some(parse_seconds(&flag, &value()?)?),
+                "--checkpoint" => parsed.checkpoint = Some(PathBuf::from(value()?)),
+                "--resume" => parsed.resume = Some(PathBuf::from(value()?)),
+                "--adaptive" => parsed.adaptive = true,
+                "--adaptive-threshold" => {
+                    parsed.adaptive_threshold = Some(parse_value(&flag, &value()?)?)
+                }
+                "--min-samples" => parsed.min_samples = Some(parse_value(&flag, &value()?)?),
+                "--max-samples" => parsed.max_samples = Some(parse_value(&flag, &value()?)?),
+                "--sample-heatmap" => parsed.sample_heatmap = Some(PathBuf::from(value()?)),
+                "--samples" => parsed.set_int(&flag, &["Camera", "samples"], value()?)?,
+                "--max-depth" => parsed.set_int(&flag, &["Camera", "max_depth"], value()?)?,
+                "--width" => parsed.set_int(&flag, &["Camera", "image_width"], value()?)?,
+                "--height" => parsed.set_int(&flag, &["Camera", "image_height"], value()?)?,
+                "--seed" => parsed.set_int(&flag, &["Seed"], value()?)?,
+                "--sampler" => {
+                    let value = value()?;
+                    parse_value::<SamplerKind>(&flag, &value)?;
+                    parsed
+                        .overrides
+                        .push(Override::new(&["Camera", "sampler"], value));
+                }
+                "--stats-json" => parsed.stats_json = Some(PathBuf::from(value()?)),
+                "--set" => {
+                    let value = value()?;
+                    let o = value
+                        .parse()
+                        .map_err(|_| CliError::InvalidValue(flag.clone(), value))?;
+                    parsed.overrides.push(o);
+                }
+                _ if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(CliError::UnknownOption(flag))
+                }
+                _ if scene.is_none() => scene = Some(arg),
+                _ => return Err(CliError::UnknownOption(arg)),
+            }
+        }
+
+        match scene {
+            Some(scene) => parsed.scene = scene,
+            None if parsed.help => {}
+            None => return Err(CliError::MissingScene),
+        }
+
+        Ok(parsed)
+    }
+
+    /// The progressive settings, if `--progressive` or any of its options
+    /// were given.
+    pub fn progressive_options(&self) -> Option<ProgressiveOptions> {
+        let adaptive = self.adaptive_options();
+        let any = self.progressive
+            || adaptive.is_some()
+            || self.sample_heatmap.is_some()
+            || self.pass_samples.is_some()
+            || self.snapshot_passes.is_some()
+            || self.snapshot_seconds.is_some()
+            || self.time_limit.is_some()
+            || self.checkpoint_path().is_some();
+        let default_interval = match (self.checkpoint_path(), self.snapshot_passes) {
+            (Some(_), None) => Some(CHECKPOINT_INTERVAL),
+            _ => None,
+        };
+        any.then(|| ProgressiveOptions {
+            pass_samples: self
+                .pass_samples
+                .unwrap_or(if adaptive.is_some() { 8 } else { 1 }),
+            target_samples: None,
+            time_limit: self.time_limit,
+            snapshot_passes: self.snapshot_passes,
+            snapshot_interval: self.snapshot_seconds.or(default_interval),
+            adaptive,
+        })
+    }
+
+    /// The adaptive sampling settings, if `--adaptive` or any of its options
+    /// were given.
+    pub fn adaptive_options(&self) -> Option<AdaptiveOptions> {
+        let any = self.adaptive
+            || self.adaptive_threshold.is_some()
+            || self.min_samples.is_some()
+            || self.max_samples.is_some();
+        let defaults = AdaptiveOptions::default();
+        any.then(|| AdaptiveOptions {
+            min_samples: self.min_samples.unwrap_or(defaults.min_samples),
+            max_samples: self.max_samples,
+            threshold: self.adaptive_threshold.unwrap_or(defaults.threshold),
+        })
+    }
+
+    /// Where to save checkpoints, if anywhere.
+    pub fn checkpoint_path(&self) -> Option<&PathBuf> {
+        self.checkpoint.as_ref().or(self.resume.as_ref())
+    }
+
+    fn set_int(&mut self, flag: &str, path: &[&str], value: String) -> Result<(), CliError> {
+        parse_value::<u64>(flag, &value)?;
+        self.overrides.push(Override::new(path, value));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, CliError> {
+        Args::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_scene_only() {
+        let args = parse(&["scene.kdl"]).unwrap();
+        assert_eq!(args.scene, "scene.kdl");
+        assert_eq!(args.output, PathBuf::from("output.png"));
+        assert_eq!(args.threads, None);
+        assert!(!args.single_threaded);
+        assert!(!args.quiet);
+        assert!(args.overrides.is_empty());
+        assert_eq!(args.progressive_options(), None);
+    }
+
+    #[test]
+    fn test_progressive() {
+        let args = parse(&["scene.kdl", "--time-limit", "1.5", "--snapshot-passes=4"]).unwrap();
+        let options = args.progressive_options().unwrap();
+        assert_eq!(options.pass_samples, 1);
+        assert_eq!(options.time_limit, Some(Duration::from_millis(1500)));
+        assert_eq!(options.snapshot_passes, Some(4));
+        assert_eq!(options.snapshot_interval, None);
+        assert_eq!(options.adaptive, None);
+
+        assert!(parse(&["scene.kdl", "--progressive"])
+            .unwrap()
+            .progressive_options()
+            .is_some());
+
+        let args = parse(&["scene.kdl", "--resume", "render.ckpt"]).unwrap();
+        assert_eq!(args.checkpoint_path(), Some(&PathBuf::from("render.ckpt")));
+        let options = args.progressive_options().unwrap();
+        assert_eq!(options.snapshot_interval, Some(CHECKPOINT_INTERVAL));
+
+        assert_eq!(
+            parse(&["scene.kdl", "--snapshot-seconds", "-1"]),
+            Err(CliError::InvalidValue(
+                "--snapshot-seconds".into(),
+                "-1".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_adaptive() {
+        let args = parse(&[
+            "scene.kdl",
+            "--min-samples",
+            "4",
+            "--sample-heatmap=heat.png",
+        ])
+        .unwrap();
+        assert_eq!(args.sample_heatmap, Some(PathBuf::from("heat.png")));
+        let options = args.progressive_options().unwrap();
+        assert_eq!(options.pass_samples, 8);
+        assert_eq!(
+            options.adaptive,
+            Some(AdaptiveOptions {
+                min_samples: 4,
+                max_samples: None,
+                threshold: 0.01,
+            })
+        );
+
+        let args = parse(&["scene.kdl", "--adaptive", "--adaptive-threshold", "0.05"]).unwrap();
+        let adaptive = args.adaptive_options().unwrap();
+        assert_eq!(adaptive.min_samples, 16);
+        assert_eq!(adaptive.threshold, 0.05);
+    }
+
+    #[test]
+    fn test_options() {
+        let args = parse(&[
+            "-o",
+            "out.exr",
+            "--threads=4",
+            "scene.kdl",
+            "--single-threaded",
+            "--format",
+            "ppm-ascii",
+            "--tile-size",
+            "32",
+            "--tile-order=hilbert",
+            "--stats-json",
+            "stats.json",
+            "-q",
+        ])
+        .unwrap();
+        assert_eq!(args.scene, "scene.kdl");
+        assert_eq!(args.output, PathBuf::from("out.exr"));
+        assert_eq!(args.format, Some(OutputFormat::PpmAscii));
+        assert_eq!(args.threads, Some(4));
+        assert!(args.single_threaded);
+        assert_eq!(args.tile_size, Some(32));
+        assert_eq!(args.tile_order, Some(TileOrder::Hilbert));
+        assert_eq!(args.stats_json, Some(PathBuf::from("stats.json")));
+        assert!(args.quiet);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let args = parse(&[
+            "scene.kdl",
+            "--samples",
+            "16",
+            "--width=320",
+            "--seed",
+            "42",
+            "--sampler=sobol",
+            "--set",
+            "camera.vfov=30",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.overrides,
+            vec![
+                Override::new(&["Camera", "samples"], "16".into()),
+                Override::new(&["Camera", "image_width"], "320".into()),
+                Override::new(&["Seed"], "42".into()),
+                Override::new(&["Camera", "sampler"], "sobol".into()),
+                Override::new(&["camera", "vfov"], "30".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse(&[]), Err(CliError::MissingScene));
+        assert_eq!(
+            parse(&["scene.kdl", "--threads"]),
+            Err(CliError::MissingValue("--threads".into()))
+        );
+        assert_eq!(
+            parse(&["scene.kdl", "--samples", "many"]),
+            Err(CliError::InvalidValue("--samples".into(), "many".into()))
+        );
+        assert_eq!(
+            parse(&["scene.kdl", "--bogus"]),
+            Err(CliError::UnknownOption("--bogus".into()))
+        );
+        assert_eq!(
+            parse(&["scene.kdl", "--sampler", "blue"]),
+            Err(CliError::InvalidValue("--sampler".into(), "blue".into()))
+        );
+        assert!(parse(&["--help"]).unwrap().help);
+    }
+}
@@ -1,4 +1,4 @@
-use image::{Rgb, Rgba};
+use image::Rgb;
 
 use crate::math::Vec3;
 use crate::{interval::Interval, util::linear_to_gamma};
@@ -26,6 +26,11 @@ impl Color {
         self.0[2]
     }
 
+    /// Relative luminance, for linear values with Rec. 709 primaries.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+    }
+
     pub fn to_pixel(&self) -> (u8, u8, u8) {
         let r = self.r();
         let g = self.g();
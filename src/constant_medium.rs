@@ -1,6 +1,6 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
-use rand::random;
+use rand::Rng as _;
 
 use crate::{
     color::Color,
@@ -9,6 +9,7 @@ use crate::{
     math::Vec3,
     object::{Hit, Object},
     ray::Ray,
+    rng::Rng,
     solid_color::SolidColor,
     texture::Texture,
     vec3,
@@ -39,12 +40,12 @@ impl ConstantMedium {
 }
 
 impl Object for ConstantMedium {
-    fn hit(&self, r: &Ray, ray_t: &crate::interval::Interval) -> Option<crate::object::Hit> {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> Option<Hit> {
         self.boundary
-            .hit(r, &Interval::universe())
+            .hit(r, &Interval::universe(), rng)
             .and_then(|hit1| {
                 self.boundary
-                    .hit(r, &Interval::from(hit1.t + 0.0001))
+                    .hit(r, &Interval::from(hit1.t + 0.0001), rng)
                     .map(|hit2| (hit1.t, hit2.t))
             })
             .filter(|(mut t1, mut t2)| {
@@ -63,7 +64,7 @@ impl Object for ConstantMedium {
 
                 let ray_length = r.direction.length();
                 let distance_inside_boundary = (t2 - t1) * ray_length;
-                let hit_distance = self.neg_inv_density * random::<f64>().ln();
+                let hit_distance = self.neg_inv_density * rng.random::<f64>().ln();
 
                 if hit_distance > distance_inside_boundary {
                     None
@@ -99,14 +100,20 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(
-        &self,
-        r_in: &crate::ray::Ray,
-        hit: &crate::object::Hit,
-    ) -> Option<crate::material::Scatter> {
-        Some(Scatter {
-            ray: Ray::new(hit.p, Vec3::random_unit(), r_in.time),
-            att: self.0.sample_tex(&hit.uv, &hit.p),
-        })
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut Rng) -> Option<Scatter> {
+        // The phase function is constant, so it cancels out against the pdf.
+        Some(Scatter::diffuse(
+            self.0.sample_tex(&hit.uv, &hit.p),
+            Ray::new(hit.p, Vec3::random_unit(rng), r_in.time),
+            1.0 / (4.0 * PI),
+        ))
+    }
+
+    fn eval(&self, _r_in: &Ray, hit: &Hit, _scattered: &Ray) -> Color {
+        self.0.sample_tex(&hit.uv, &hit.p) / (4.0 * PI)
+    }
+
+    fn pdf(&self, _r_in: &Ray, _hit: &Hit, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
     }
 }
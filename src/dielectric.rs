@@ -1,11 +1,10 @@
-use rand::random;
-
 use crate::{
     color::Color,
     material::{Material, Scatter},
     object::Hit,
     ray::Ray,
     rgb,
+    rng::Rng,
 };
 
 pub struct Dielectric {
@@ -20,7 +19,7 @@ fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<Scatter> {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut Rng) -> Option<Scatter> {
         let ri = if hit.front_face {
             1.0 / self.refraction_index
         } else {
@@ -31,15 +30,15 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = ri * sin_theta > 1.0;
-        let direction = if cannot_refract || reflectance(cos_theta, ri) > random() {
+        let direction = if cannot_refract || reflectance(cos_theta, ri) > rng.get_1d() {
             unit_direction.reflect(&hit.normal)
         } else {
             unit_direction.refract(&hit.normal, ri)
         };
 
-        Some(Scatter {
-            att: rgb!(1.0, 1.0, 1.0),
-            ray: Ray::new(hit.p, direction, r_in.time),
-        })
+        Some(Scatter::specular(
+            rgb!(1.0, 1.0, 1.0),
+            Ray::new(hit.p, direction, r_in.time),
+        ))
     }
 }
@@ -21,4 +21,8 @@ impl Material for DiffuseLight {
     fn emitted(&self, _r_in: &Ray, hit: &Hit) -> crate::color::Color {
         self.tex.sample_tex(&hit.uv, &hit.p)
     }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
 }
@@ -1,7 +1,27 @@
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
+    #[error("Can't resume from checkpoint, {0}")]
+    Checkpoint(String),
     #[error(transparent)]
     ExpressionError(#[from] exmex::ExError),
     #[error(transparent)]
     ImageError(#[from] image::ImageError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse model, {0}")]
+    Model(String),
+    #[error("Unknown reconstruction filter {0:?}")]
+    UnknownFilter(String),
+    #[error("Unknown output format {0:?}")]
+    UnknownFormat(String),
+    #[error("Unknown sampler {0:?}")]
+    UnknownSampler(String),
+    #[error("Unknown shutter curve {0:?}")]
+    UnknownShutterCurve(String),
+    #[error("Unknown BVH split method {0:?}")]
+    UnknownSplitMethod(String),
+    #[error("Unknown tile order {0:?}")]
+    UnknownTileOrder(String),
+    #[error("Unknown tone mapping operator {0:?}")]
+    UnknownTonemap(String),
 }
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
 
 use exmex::{
     ArrayType, ExError, Express, FlatEx, MakeOperators, Operator, Val, ValMatcher, ValOpsFactory,
@@ -6,7 +6,7 @@ use exmex::{
 
 use crate::{
     math::{Vec2, Vec3, Vector},
-    perlin::PERLIN,
+    perlin::Perlin,
     vec3,
 };
 
@@ -55,18 +55,36 @@ impl<const N: usize> Into<Val> for &Vector<N> {
     }
 }
 
+thread_local! {
+    // The noise of the expression being evaluated, as operators can't carry
+    // any state of their own. Only set while `Expression::eval` runs.
+    static PERLIN: RefCell<Option<Arc<Perlin>>> = const { RefCell::new(None) };
+}
+
+fn with_perlin(f: impl FnOnce(&Perlin) -> f64) -> f64 {
+    PERLIN.with(|perlin| {
+        let perlin = perlin.borrow();
+        f(perlin
+            .as_deref()
+            .expect("noise evaluated outside Expression::eval"))
+    })
+}
+
 #[derive(Clone, Debug)]
 struct NoiseOpsFactory;
 impl MakeOperators<Val> for NoiseOpsFactory {
     fn make<'a>() -> Vec<Operator<'a, Val>> {
         let mut ops = ValOpsFactory::make();
         ops.push(Operator::make_unary("noise", |a| {
-            Val::Float(PERLIN.noise(&a.into()))
+            Val::Float(with_perlin(|perlin| perlin.noise(&a.into())))
         }));
         ops.push(Operator::make_bin(
             "turb",
             exmex::BinOp {
-                apply: |a, b| Val::Float(PERLIN.turb(&a.into(), b.to_int().unwrap_or_default())),
+                apply: |a, b| {
+                    let depth = b.to_int().unwrap_or_default();
+                    Val::Float(with_perlin(|perlin| perlin.turb(&a.into(), depth)))
+                },
                 prio: 0,
                 is_commutative: false,
             },
@@ -77,22 +95,54 @@ impl MakeOperators<Val> for NoiseOpsFactory {
 
 type FlatExNoise = FlatEx<Val, NoiseOpsFactory, ValMatcher>;
 
-pub struct Expression(FlatExNoise);
+/// An expression whose `noise` and `turb` operators use `perlin`.
+pub struct Expression {
+    expr: FlatExNoise,
+    perlin: Arc<Perlin>,
+}
 
 impl Expression {
-    pub fn parse(expr: &str) -> Result<Self, ExError> {
-        let expr = FlatExNoise::parse(expr)?;
-        Ok(Self(expr))
+    pub fn parse(expr: &str, perlin: &Arc<Perlin>) -> Result<Self, ExError> {
+        Ok(Self {
+            expr: FlatExNoise::parse(expr)?,
+            perlin: Arc::clone(perlin),
+        })
     }
 
     pub fn eval(&self, vars: HashMap<&str, Val>) -> Result<Val, ExError> {
-        let var_names = self.0.var_names();
+        let var_names = self.expr.var_names();
         let mut pairs: Vec<(&str, Val)> = vars
             .into_iter()
             .filter(|(k, _)| var_names.contains(&k.to_string()))
             .collect();
         pairs.sort_unstable_by_key(|(k, _)| *k);
         let vars: Vec<Val> = pairs.into_iter().map(|(_, v)| v).collect();
-        self.0.eval_vec(vars)
+        let outer = PERLIN.replace(Some(Arc::clone(&self.perlin)));
+        let val = self.expr.eval_vec(vars);
+        PERLIN.set(outer);
+        val
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_eval_noise() {
+        let perlin = Arc::new(Perlin::from_seed(3));
+        let expr = Expression::parse("noise(p)", &perlin).unwrap();
+        let p = vec3!(0.3, 1.7, -2.2);
+        let val = expr.eval(HashMap::from([("p", p.into())])).unwrap();
+        assert_eq!(val.to_float().unwrap(), perlin.noise(&p));
+        assert!(PERLIN.with(|perlin| perlin.borrow().is_none()));
+    }
+
+    #[test]
+    #[should_panic(expected = "outside Expression::eval")]
+    fn test_noise_outside_eval() {
+        let _ = FlatExNoise::parse("noise(p)")
+            .unwrap()
+            .eval_vec(vec![vec3!(0.3, 1.7, -2.2).into()]);
     }
 }
@@ -0,0 +1,197 @@
+use std::str::FromStr;
+
+use crate::{color::Color, error::Error, filter::Filter, rgb};
+
+/// Maps scene radiance into the [0, 1] range of a display.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tonemap {
+    /// Cuts off everything brighter than 1.
+    #[default]
+    Clamp,
+    /// `c / (1 + c)` per channel.
+    Reinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// Troy Sobotka's AgX, using the minimal polynomial approximation.
+    Agx,
+}
+
+impl FromStr for Tonemap {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clamp" => Ok(Self::Clamp),
+            "reinhard" => Ok(Self::Reinhard),
+            "aces" => Ok(Self::Aces),
+            "agx" => Ok(Self::Agx),
+            _ => Err(Error::UnknownTonemap(s.to_string())),
+        }
+    }
+}
+
+fn map_channels(c: &Color, f: impl Fn(f64) -> f64) -> Color {
+    rgb!(f(c.r()), f(c.g()), f(c.b()))
+}
+
+fn aces(x: f64) -> f64 {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    ((x * (a * x + b)) / (x * (c * x + d) + e)).clamp(0.0, 1.0)
+}
+
+// Rows of the AgX inset matrix and its inverse, for linear sRGB primaries.
+const AGX_INSET: [[f64; 3]; 3] = [
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+];
+const AGX_OUTSET: [[f64; 3]; 3] = [
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+];
+const AGX_MIN_EV: f64 = -12.47393;
+const AGX_MAX_EV: f64 = 4.026069;
+
+fn mul(m: &[[f64; 3]; 3], c: &Color) -> Color {
+    let row = |r: &[f64; 3]| r[0] * c.r() + r[1] * c.g() + r[2] * c.b();
+    rgb!(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+fn agx_contrast(x: f64) -> f64 {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+        - 0.00232
+}
+
+fn agx(c: &Color) -> Color {
+    let c = mul(&AGX_INSET, c);
+    let c = map_channels(&c, |x| {
+        let ev = x.max(1e-10).log2().clamp(AGX_MIN_EV, AGX_MAX_EV);
+        agx_contrast((ev - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV))
+    });
+    let c = mul(&AGX_OUTSET, &c);
+    // The curve produces display encoded values; bring them back to linear so
+    // every operator hands the same thing to the transfer function.
+    map_channels(&c, |x| x.max(0.0).powf(2.2))
+}
+
+impl Tonemap {
+    pub fn apply(&self, c: &Color) -> Color {
+        match self {
+            Self::Clamp => map_channels(c, |x| x.clamp(0.0, 1.0)),
+            Self::Reinhard => map_channels(c, |x| {
+                let x = x.max(0.0);
+                x / (1.0 + x)
+            }),
+            Self::Aces => map_channels(c, aces),
+            Self::Agx => agx(c),
+        }
+    }
+}
+
+/// The sRGB opto-electronic transfer function.
+pub fn linear_to_srgb(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Turns the linear radiance of a frame into display values.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Film {
+    /// Exposure adjustment in stops.
+    pub exposure: f64,
+    pub tonemap: Tonemap,
+    /// How samples are spread over the pixels around them.
+    pub filter: Filter,
+}
+
+impl Film {
+    /// Scales `c` by the exposure, leaving it linear and unbounded.
+    pub fn expose(&self, c: &Color) -> Color {
+        *c * self.exposure.exp2()
+    }
+
+    /// Exposes, tone maps and sRGB encodes `c`, giving values in [0, 1].
+    pub fn develop(&self, c: &Color) -> Color {
+        let mapped = self.tonemap.apply(&self.expose(c));
+        map_channels(&mapped, |x| linear_to_srgb(x.clamp(0.0, 1.0)))
+    }
+
+    /// [`Film::develop`] quantized to 8 bits.
+    pub fn to_pixel(&self, c: &Color) -> (u8, u8, u8) {
+        let c = self.develop(c);
+        let byte = |x: f64| (x * 255.0).round() as u8;
+        (byte(c.r()), byte(c.g()), byte(c.b()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assert_in_delta;
+
+    use super::*;
+
+    const ALL: [Tonemap; 4] = [
+        Tonemap::Clamp,
+        Tonemap::Reinhard,
+        Tonemap::Aces,
+        Tonemap::Agx,
+    ];
+
+    #[test]
+    fn test_srgb() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert_in_delta!(linear_to_srgb(1.0), 1.0);
+        assert_in_delta!(linear_to_srgb(0.002), 0.02584);
+        assert_in_delta!(linear_to_srgb(0.5), 0.735356, 1e-6);
+    }
+
+    #[test]
+    fn test_tonemaps_stay_in_range() {
+        for tonemap in ALL {
+            for x in [0.0, 0.01, 0.18, 1.0, 4.0, 100.0, 1e6] {
+                let c = tonemap.apply(&rgb!(x));
+                assert!((0.0..=1.0).contains(&c.r()), "{:?} {} {:?}", tonemap, x, c);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tonemaps_are_monotonic() {
+        for tonemap in ALL {
+            let mut last = -1.0;
+            for x in [0.0, 0.01, 0.18, 1.0, 4.0, 100.0] {
+                let y = tonemap.apply(&rgb!(x)).g();
+                assert!(y >= last, "{:?} {}", tonemap, x);
+                last = y;
+            }
+        }
+    }
+
+    #[test]
+    fn test_exposure() {
+        let film = Film {
+            exposure: 1.0,
+            tonemap: Tonemap::Clamp,
+            ..Default::default()
+        };
+        assert_eq!(film.expose(&rgb!(0.25)), rgb!(0.5));
+        assert_eq!(film.to_pixel(&rgb!(0.5)), (255, 255, 255));
+        assert_eq!(Film::default().to_pixel(&rgb!(0.0)), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_parse_tonemap() {
+        assert_eq!("ACES".parse::<Tonemap>().unwrap(), Tonemap::Aces);
+        assert!("filmic".parse::<Tonemap>().is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::{f64::consts::PI, str::FromStr};
+
+use crate::error::Error;
+
+/// The shape of a reconstruction filter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilterKind {
+    /// Every sample counts fully for the pixels within the radius.
+    #[default]
+    Box,
+    /// Weights fall off linearly to zero at the radius.
+    Tent,
+    /// A Gaussian with a standard deviation of a third of the radius, shifted
+    /// to reach zero at the radius.
+    Gaussian,
+    /// The Mitchell–Netravali cubic with B = C = 1/3.
+    Mitchell,
+    /// A sinc windowed by a sinc as wide as the radius.
+    Lanczos,
+}
+
+impl FromStr for FilterKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "box" => Ok(Self::Box),
+            "tent" | "triangle" => Ok(Self::Tent),
+            "gaussian" => Ok(Self::Gaussian),
+            "mitchell" => Ok(Self::Mitchell),
+            "lanczos" => Ok(Self::Lanczos),
+            _ => Err(Error::UnknownFilter(s.to_string())),
+        }
+    }
+}
+
+impl FilterKind {
+    /// The radius used when none is given, in pixels.
+    pub fn default_radius(&self) -> f64 {
+        match self {
+            Self::Box => 0.5,
+            Self::Tent => 1.0,
+            Self::Gaussian => 1.5,
+            Self::Mitchell | Self::Lanczos => 2.0,
+        }
+    }
+}
+
+/// Weighs a sample's contribution to the pixels around it by their distance.
+/// The default box of radius 0.5 gives every sample only to its own pixel,
+/// which is plain averaging.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    /// How far from a sample, in pixels, pixel centers still receive it.
+    pub radius: f64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(FilterKind::default())
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn gaussian(x: f64, sigma: f64) -> f64 {
+    (-x * x / (2.0 * sigma * sigma)).exp()
+}
+
+fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1.0 / 3.0;
+    const C: f64 = 1.0 / 3.0;
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * B - 6.0 * C) * x * x * x
+            + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+            + (6.0 - 2.0 * B))
+            / 6.0
+    } else if x < 2.0 {
+        ((-B - 6.0 * C) * x * x * x
+            + (6.0 * B + 30.0 * C) * x * x
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+impl Filter {
+    /// A filter of `kind` with its default radius.
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+
+    /// The weight of a sample `(dx, dy)` pixels away from a pixel center.
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, x: f64) -> f64 {
+        let r = self.radius;
+        match self.kind {
+            // Half open, so a sample on the edge between two pixels only
+            // counts for one of them.
+            FilterKind::Box => {
+                if -r <= x && x < r {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            FilterKind::Tent => (1.0 - x.abs() / r).max(0.0),
+            FilterKind::Gaussian => (gaussian(x, r / 3.0) - gaussian(r, r / 3.0)).max(0.0),
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => {
+                if x.abs() < r {
+                    sinc(x) * sinc(x / r)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// How many pixels beyond its own a sample can reach.
+    pub fn margin(&self) -> usize {
+        (self.radius - 0.5).max(0.0).ceil() as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "Mitchell".parse::<FilterKind>().unwrap(),
+            FilterKind::Mitchell
+        );
+        assert_eq!("triangle".parse::<FilterKind>().unwrap(), FilterKind::Tent);
+        assert!("sharp".parse::<FilterKind>().is_err());
+    }
+
+    #[test]
+    fn test_weights() {
+        for kind in [
+            FilterKind::Box,
+            FilterKind::Tent,
+            FilterKind::Gaussian,
+            FilterKind::Mitchell,
+            FilterKind::Lanczos,
+        ] {
+            let filter = Filter::new(kind);
+            assert!(filter.weight(0.0, 0.0) > 0.0, "{:?}", kind);
+            assert_eq!(filter.weight(filter.radius + 0.1, 0.0), 0.0, "{:?}", kind);
+            assert_eq!(
+                filter.weight(0.3, -0.2),
+                filter.weight(-0.3, 0.2),
+                "{:?}",
+                kind
+            );
+        }
+
+        let filter = Filter::default();
+        assert_eq!(filter.margin(), 0);
+        assert_eq!(filter.weight(-0.5, 0.0), 1.0);
+        assert_eq!(filter.weight(0.5, 0.0), 0.0);
+        assert_eq!(Filter::new(FilterKind::Mitchell).margin(), 2);
+        assert!(Filter::new(FilterKind::Lanczos).weight(1.5, 0.0) < 0.0);
+    }
+}
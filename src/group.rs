@@ -1,8 +1,10 @@
 use crate::{
     aabb::AABB,
     interval::Interval,
-    object::{Hit, Object},
+    math::{Point3, Vec3},
+    object::{uniform_pdf_value, uniform_random, Hit, Object},
     ray::Ray,
+    rng::Rng,
 };
 
 #[derive(Default)]
@@ -27,12 +29,12 @@ impl Group {
 }
 
 impl Object for Group {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<Hit> {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> Option<Hit> {
         let mut rec: Option<Hit> = None;
         let mut closest = ray_t.max;
 
         for object in &self.objects {
-            if let Some(hit) = object.hit(r, &Interval::new(ray_t.min, closest)) {
+            if let Some(hit) = object.hit(r, &Interval::new(ray_t.min, closest), rng) {
                 closest = hit.t;
                 rec = Some(hit)
             }
@@ -44,4 +46,18 @@ impl Object for Group {
     fn bbox(&self) -> &AABB {
         &self.bbox
     }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64, rng: &mut Rng) -> f64 {
+        uniform_pdf_value(&self.objects, origin, direction, time, rng)
+    }
+
+    fn random(&self, origin: &Point3, time: f64, rng: &mut Rng) -> Vec3 {
+        uniform_random(&self.objects, origin, time, rng)
+    }
+
+    fn collect_lights(&self, lights: &mut Vec<Box<dyn Object>>) {
+        for object in &self.objects {
+            object.collect_lights(lights);
+        }
+    }
 }
@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    interval::Interval,
+    material::Material,
+    math::{Point3, Vec3},
+    object::{Hit, Object},
+    ray::Ray,
+    rng::Rng,
+};
+
+/// A placement of shared geometry, optionally drawn with a different
+/// material. Wrap it in a [`Transform`](crate::transform::Transform) to move
+/// it.
+pub struct Instance {
+    pub obj: Arc<dyn Object>,
+    pub mat: Option<Arc<dyn Material>>,
+}
+
+impl Instance {
+    pub fn new(obj: &Arc<dyn Object>, mat: Option<Arc<dyn Material>>) -> Self {
+        Self {
+            obj: Arc::clone(obj),
+            mat,
+        }
+    }
+}
+
+impl Object for Instance {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> Option<Hit> {
+        let mut hit = self.obj.hit(r, ray_t, rng)?;
+        if let Some(mat) = &self.mat {
+            hit.mat = Arc::clone(mat);
+        }
+        Some(hit)
+    }
+
+    fn bbox(&self) -> &AABB {
+        self.obj.bbox()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64, rng: &mut Rng) -> f64 {
+        self.obj.pdf_value(origin, direction, time, rng)
+    }
+
+    fn random(&self, origin: &Point3, time: f64, rng: &mut Rng) -> Vec3 {
+        self.obj.random(origin, time, rng)
+    }
+
+    // An overriding material replaces whatever the shared geometry emits. An
+    // emissive one makes the whole instance a single light, any other leaves
+    // it dark.
+    fn collect_lights(&self, lights: &mut Vec<Box<dyn Object>>) {
+        match &self.mat {
+            None => self.obj.collect_lights(lights),
+            Some(mat) if mat.is_emissive() => {
+                lights.push(Box::new(Instance::new(&self.obj, Some(Arc::clone(mat)))))
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use crate::{
+        camera::Camera, color::Color, diffuse_light::DiffuseLight, group::Group,
+        lambertian::Lambertian, point, quad::Quad, rgb, scene::Scene, vec3,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_collect_lights() {
+        let white: Arc<dyn Material> = Arc::new(Lambertian::solid(rgb!(0.5)));
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::solid(rgb!(4.0)));
+        let shape: Arc<dyn Object> = Arc::new(Group::new(vec![Box::new(Quad::new(
+            point!(-0.5, 1.0, -1.5),
+            vec3!(1.0, 0.0, 0.0),
+            vec3!(0.0, 0.0, 1.0),
+            &white,
+        ))]));
+        let world = Group::new(vec![
+            Box::new(Instance::new(&shape, None)),
+            Box::new(Instance::new(&shape, Some(Arc::clone(&light)))),
+            Box::new(Instance::new(&shape, Some(Arc::clone(&white)))),
+        ]);
+        let camera = Camera::new(
+            4,
+            4,
+            90.0,
+            point!(0.0, 0.0, 0.0),
+            point!(0.0, 0.0, -1.0),
+            vec3!(0.0, 1.0, 0.0),
+            0.0,
+            1.0,
+            1,
+            1,
+        );
+        let scene = Scene::new(camera, Box::new(world), None).unwrap();
+
+        // Only the instance drawn with the light, and it can be sampled.
+        assert_eq!(scene.lights.len(), 1);
+        let mut rng = Rng::seed_from_u64(0);
+        let origin = point!(0.0, 0.0, -1.0);
+        let direction = scene.lights[0].random(&origin, 0.0, &mut rng);
+        assert!(scene.lights[0].pdf_value(&origin, &direction, 0.0, &mut rng) > 0.0);
+    }
+}
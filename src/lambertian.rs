@@ -1,11 +1,13 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 use crate::{
     color::Color,
     material::{Material, Scatter},
     math::Vec3,
     object::Hit,
+    onb::Onb,
     ray::Ray,
+    rng::Rng,
     solid_color::SolidColor,
     texture::Texture,
 };
@@ -23,16 +25,86 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<Scatter> {
-        let mut scatter_direction = hit.normal + Vec3::random_unit();
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut Rng) -> Option<Scatter> {
+        let uvw = Onb::new(&hit.normal);
+        let scattered = Ray::new(
+            hit.p,
+            uvw.transform(&Vec3::random_cosine_direction(rng)),
+            r_in.time,
+        );
+        let pdf = self.pdf(r_in, hit, &scattered);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        // The cosine and 1/π of the BSDF cancel out against the pdf.
+        Some(Scatter::diffuse(
+            self.tex.sample_tex(&hit.uv, &hit.p),
+            scattered,
+            pdf,
+        ))
+    }
+
+    fn eval(&self, r_in: &Ray, hit: &Hit, scattered: &Ray) -> Color {
+        self.tex.sample_tex(&hit.uv, &hit.p) * self.pdf(r_in, hit, scattered)
+    }
 
-        if scatter_direction.near_zero() {
-            scatter_direction = hit.normal;
+    fn pdf(&self, _r_in: &Ray, hit: &Hit, scattered: &Ray) -> f64 {
+        let cos_theta = hit.normal.dot(&scattered.direction.unit());
+        if cos_theta < 0.0 {
+            0.0
+        } else {
+            cos_theta / PI
         }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use crate::{
+        assert_in_delta,
+        math::{Point3, Vec2},
+        point, rgb, vec2, vec3,
+    };
+
+    use super::*;
+
+    fn hit(mat: &Arc<dyn Material>) -> Hit {
+        let r = Ray::new(point!(0.0, 1.0, 0.0), vec3!(0.0, -1.0, 0.0), 0.0);
+        Hit::new(
+            1.0,
+            point!(0.0, 0.0, 0.0),
+            &r,
+            vec3!(0.0, 1.0, 0.0),
+            vec2!(0.0, 0.0),
+            mat,
+        )
+    }
+
+    #[test]
+    fn test_scatter_matches_eval() {
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::solid(rgb!(0.5)));
+        let r = Ray::new(point!(0.0, 1.0, 0.0), vec3!(0.0, -1.0, 0.0), 0.0);
+        let hit = hit(&mat);
+        let mut rng = Rng::seed_from_u64(0);
+        for _ in 0..100 {
+            let scatter = mat.scatter(&r, &hit, &mut rng).unwrap();
+            assert!(!scatter.specular);
+            assert!(scatter.ray.direction.dot(&hit.normal) >= 0.0);
+            assert_in_delta!(scatter.pdf, mat.pdf(&r, &hit, &scatter.ray));
+            let weight = mat.eval(&r, &hit, &scatter.ray) / scatter.pdf;
+            assert_in_delta!(weight.r(), scatter.att.r());
+        }
+    }
 
-        Some(Scatter {
-            att: self.tex.sample_tex(&hit.uv, &hit.p),
-            ray: Ray::new(hit.p, scatter_direction, r_in.time),
-        })
+    #[test]
+    fn test_pdf_below_surface() {
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::solid(rgb!(0.5)));
+        let r = Ray::new(point!(0.0, 1.0, 0.0), vec3!(0.0, -1.0, 0.0), 0.0);
+        let below = Ray::new(point!(0.0, 0.0, 0.0), vec3!(0.0, -1.0, 0.0), 0.0);
+        assert_eq!(mat.pdf(&r, &hit(&mat), &below), 0.0);
+        assert_eq!(mat.eval(&r, &hit(&mat), &below), rgb!(0.0));
     }
 }
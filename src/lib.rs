@@ -0,0 +1,95 @@
+//! A small path tracer that renders scenes described in [KDL](https://kdl.dev).
+//!
+//! Scenes are usually loaded from a file with [`load_scene`], but every part
+//! of the scene graph ([`Camera`], [`Object`], [`Material`], [`Texture`], ...)
+//! is public so tools can build or tweak scenes in code.
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use yarr_tracer::{load_scene, render_to_buffer, write_image, OutputFormat};
+//!
+//! let scene = load_scene("cornell_box.kdl".into()).unwrap();
+//! let image = render_to_buffer(&scene);
+//! write_image(&image, &scene.film, Path::new("cornell.png"), OutputFormat::Png).unwrap();
+//! ```
+
+pub mod aabb;
+pub mod background;
+pub mod bvh;
+pub mod camera;
+pub mod checker;
+pub mod checkpoint;
+pub mod color;
+pub mod constant_medium;
+pub mod dielectric;
+pub mod diffuse_light;
+pub mod error;
+pub mod expression;
+pub mod film;
+pub mod filter;
+pub mod group;
+pub mod image;
+pub mod instance;
+pub mod interval;
+pub mod lambertian;
+pub mod loader;
+pub mod material;
+pub mod math;
+pub mod matrix;
+pub mod mesh;
+pub mod metal;
+pub mod obj;
+pub mod object;
+pub mod onb;
+pub mod output;
+pub mod perlin;
+pub mod progress;
+pub mod progressive;
+pub mod quad;
+pub mod ray;
+pub mod rng;
+pub mod sampler;
+pub mod scene;
+pub mod shapes;
+pub mod shutter;
+pub mod solid_color;
+pub mod sphere;
+pub mod stats;
+mod test_data;
+pub mod texture;
+pub mod thread_pool;
+pub mod tile;
+pub mod transform;
+pub mod triangle;
+pub mod util;
+
+pub use camera::{Camera, FisheyeMapping, Projection, Stereo, StereoLayout};
+pub use checkpoint::Checkpoint;
+pub use color::Color;
+pub use error::Error;
+pub use film::{Film, Tonemap};
+pub use filter::{Filter, FilterKind};
+pub use loader::{load_scene, load_scene_with, LoadError, Override};
+pub use material::Material;
+pub use object::{Hit, Object};
+pub use output::{write_image, FrameBuffer, OutputFormat};
+pub use progress::{ProgressReporter, QuietProgress, TerminalProgress};
+pub use progressive::{
+    render_progressive, Accumulation, AdaptiveOptions, ProgressiveOptions, ProgressiveRender,
+};
+pub use sampler::SamplerKind;
+pub use scene::Scene;
+pub use shutter::{Shutter, ShutterCurve};
+pub use stats::RenderStats;
+pub use texture::Texture;
+pub use thread_pool::{
+    render, render_threaded, render_unthreaded, render_with_stats, RenderOptions,
+};
+pub use tile::TileOrder;
+
+/// Renders `scene` on all available CPUs and returns the linear radiance of
+/// every pixel.
+pub fn render_to_buffer(scene: &Scene) -> FrameBuffer {
+    render(scene, &RenderOptions::default())
+}
@@ -1,30 +1,44 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 
 use crate::background::{Background, BgExpr, Gradient};
-use crate::bvh::BVH;
-use crate::camera::Camera;
+use crate::bvh::{BvhOptions, BVH};
+use crate::camera::{Camera, FisheyeMapping, Projection, Stereo, StereoLayout};
 use crate::checker::Checker;
 use crate::color::Color;
 use crate::constant_medium::ConstantMedium;
 use crate::diffuse_light::DiffuseLight;
+use crate::film::Film;
+use crate::filter::Filter;
 use crate::group::Group;
 use crate::image::Image;
+use crate::instance::Instance;
 use crate::material::Material;
 use crate::math::{Vec2, Vec3};
+use crate::matrix::Mat4;
+use crate::mesh::{Mesh, MeshData};
+use crate::obj::{load_model_materials, MtlMaterial, ObjModel};
 use crate::object::Object;
-use crate::perlin::Noise;
+use crate::perlin::{Noise, Perlin};
 use crate::quad::Quad;
+use crate::sampler::SamplerKind;
 use crate::scene::Scene;
 use crate::shapes::make_box;
+use crate::shutter::Shutter;
 use crate::solid_color::SolidColor;
 use crate::sphere::Sphere;
 use crate::texture::Texture;
-use crate::transform::{RotateY, Translate};
+use crate::transform::Transform;
+use crate::triangle::Triangle;
+use crate::util::fnv1a;
 use crate::{error, rgb, vec3};
-use kdl::{KdlDocument, KdlError, KdlNode};
+use kdl::{KdlDocument, KdlEntry, KdlError, KdlNode, KdlValue};
 use miette::{Diagnostic, IntoDiagnostic, NamedSource, SourceSpan};
 
 use crate::{dielectric::Dielectric, lambertian::Lambertian, metal::Metal};
@@ -93,6 +107,137 @@ impl From<error::Error> for LoadError {
 
 type LoadResult<T = ()> = Result<T, LoadError>;
 
+/// A `key.path=value` replacement applied to the scene document before it is
+/// loaded, e.g. `camera.vfov=30`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    pub path: Vec<String>,
+    pub value: String,
+}
+
+impl Override {
+    pub fn new(path: &[&str], value: String) -> Self {
+        Self {
+            path: path.iter().map(|s| s.to_string()).collect(),
+            value,
+        }
+    }
+
+    fn parse_value(&self, token: &str, existing: Option<&KdlValue>) -> LoadResult<KdlValue> {
+        let value = match existing {
+            Some(KdlValue::Float(_)) => token.parse::<f64>().ok().map(KdlValue::Float),
+            Some(KdlValue::Integer(_)) => token.parse::<i128>().ok().map(KdlValue::Integer),
+            Some(KdlValue::Bool(_)) => token.parse::<bool>().ok().map(KdlValue::Bool),
+            Some(KdlValue::String(_)) => Some(KdlValue::String(token.to_string())),
+            _ => Some(
+                token
+                    .parse::<i128>()
+                    .map(KdlValue::Integer)
+                    .or_else(|_| token.parse::<f64>().map(KdlValue::Float))
+                    .or_else(|_| token.parse::<bool>().map(KdlValue::Bool))
+                    .unwrap_or_else(|_| KdlValue::String(token.to_string())),
+            ),
+        };
+        value.ok_or_else(|| LoadError {
+            msg: format!("Invalid value {:?} for {}", token, self.path.join(".")),
+            span: None,
+        })
+    }
+
+    fn unknown(&self) -> LoadError {
+        LoadError {
+            msg: format!("Unknown override key {}", self.path.join(".")),
+            span: None,
+        }
+    }
+
+    fn apply(&self, doc: &mut KdlDocument) -> LoadResult {
+        let (first, rest) = self.path.split_first().ok_or_else(|| LoadError {
+            msg: "Empty override path".into(),
+            span: None,
+        })?;
+
+        let known = OVERRIDABLE
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(first));
+        let nodes = doc.nodes_mut();
+        let mut node = match (position(nodes, first), known) {
+            (Some(i), _) => &mut nodes[i],
+            (None, Some((name, _))) => insert(nodes, name),
+            (None, None) => return Err(self.unknown()),
+        };
+        for (depth, key) in rest.iter().enumerate() {
+            let nodes = node.ensure_children().nodes_mut();
+            // Only keys the loader reads can be added, so a misspelled one
+            // isn't silently ignored.
+            let addable = known
+                .filter(|_| depth == 0)
+                .and_then(|(_, keys)| keys.iter().find(|k| k.eq_ignore_ascii_case(key)));
+            node = match (position(nodes, key), addable) {
+                (Some(i), _) => &mut nodes[i],
+                (None, Some(key)) => insert(nodes, key),
+                (None, None) => return Err(self.unknown()),
+            };
+        }
+
+        let values = self
+            .value
+            .split_whitespace()
+            .enumerate()
+            .map(|(i, token)| self.parse_value(token, node.get(i)))
+            .collect::<LoadResult<Vec<KdlValue>>>()?;
+
+        node.entries_mut().retain(|e| e.name().is_some());
+        for value in values {
+            node.push(KdlEntry::new(value));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Override {
+    type Err = LoadError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((path, value)) if !path.is_empty() => Ok(Self {
+                path: path.split('.').map(|k| k.to_string()).collect(),
+                value: value.to_string(),
+            }),
+            _ => Err(LoadError {
+                msg: format!("Invalid override {:?}, expected key.path=value", s),
+                span: None,
+            }),
+        }
+    }
+}
+
+// The top level nodes an override may add when the scene leaves them out,
+// with the keys each of them may be given. `Seed` is here for `--seed`.
+const OVERRIDABLE: &[(&str, &[&str])] = &[
+    ("Camera", &CAMERA_KEYS),
+    ("Film", &FILM_KEYS),
+    ("Bvh", &BVH_OPTIONS),
+    ("Seed", &[]),
+];
+
+// The node named `key`, preferring an exact match.
+fn position(nodes: &[KdlNode], key: &str) -> Option<usize> {
+    nodes
+        .iter()
+        .position(|n| n.name().value() == key)
+        .or_else(|| {
+            nodes
+                .iter()
+                .position(|n| n.name().value().eq_ignore_ascii_case(key))
+        })
+}
+
+fn insert<'a>(nodes: &'a mut Vec<KdlNode>, key: &str) -> &'a mut KdlNode {
+    nodes.push(KdlNode::new(key));
+    nodes.last_mut().unwrap()
+}
+
 fn get_vec(node: &KdlNode, key: &str) -> LoadResult<Vec3> {
     get_vec_at(&node.children().unwrap().get(key).unwrap(), 0)
 }
@@ -108,20 +253,6 @@ fn get_vec_at(node: &KdlNode, i: usize) -> LoadResult<Vec3> {
     }
 }
 
-fn get_vec2(node: &KdlNode, key: &str) -> LoadResult<Vec2> {
-    get_vec2_at(&node.children().unwrap().get(key).unwrap(), 0)
-}
-
-fn get_vec2_at(node: &KdlNode, i: usize) -> LoadResult<Vec2> {
-    match (
-        node.get(i).and_then(|x| x.as_float()),
-        node.get(i + 1).and_then(|y| y.as_float()),
-    ) {
-        (Some(x), Some(y)) => Ok(Vec2::new(x, y)),
-        _ => Err(LoadError::obj("Vec2", node)),
-    }
-}
-
 fn get_float(node: &KdlNode, key: &str) -> LoadResult<f64> {
     node.children()
         .and_then(|c| c.get_arg(key))
@@ -136,25 +267,424 @@ fn get_int(node: &KdlNode, key: &str) -> LoadResult<i128> {
         .ok_or_else(|| LoadError::obj("Integer", node))
 }
 
-fn parse_checker_tex(node: &KdlNode, key: &str) -> LoadResult<Arc<dyn Texture>> {
+fn get_string<'a>(node: &'a KdlNode, key: &str) -> LoadResult<&'a str> {
+    node.children()
+        .and_then(|c| c.get_arg(key))
+        .and_then(|a| a.as_string())
+        .ok_or_else(|| LoadError::obj("String", node))
+}
+
+fn get_float_at(node: &KdlNode, i: usize) -> LoadResult<f64> {
+    node.get(i)
+        .and_then(|a| a.as_float())
+        .ok_or_else(|| LoadError::obj("Float", node))
+}
+
+// All the arguments of `node` as floats.
+fn node_floats(node: &KdlNode) -> LoadResult<Vec<f64>> {
+    node.entries()
+        .iter()
+        .filter(|e| e.name().is_none())
+        .map(|e| e.value().as_float())
+        .collect::<Option<Vec<f64>>>()
+        .ok_or_else(|| LoadError::obj("Float list", node))
+}
+
+fn get_floats(node: &KdlNode, key: &str) -> LoadResult<Vec<f64>> {
+    match node.children().and_then(|c| c.get(key)) {
+        Some(n) => node_floats(n),
+        None => Err(LoadError::obj("Float list", node)),
+    }
+}
+
+// Either a single uniform factor or one per axis.
+fn parse_scale_factors(step: Option<&KdlNode>, parent: &KdlNode) -> LoadResult<Vec3> {
+    let Some(step) = step else {
+        return Err(LoadError::obj("scale", parent));
+    };
+    match node_floats(step)?[..] {
+        [s] => Ok(Vec3::repeat(s)),
+        [x, y, z] => Ok(vec3!(x, y, z)),
+        _ => Err(LoadError::new("scale takes one or three values", step)),
+    }
+}
+
+fn get_indices(node: &KdlNode, key: &str) -> LoadResult<Vec<usize>> {
     match node.children().and_then(|c| c.get(key)) {
-        Some(tnode) => {
-            if tnode.get(0).is_some_and(|n| n.is_string()) {
-                parse_tex(&tnode)
+        Some(n) => n
+            .entries()
+            .iter()
+            .filter(|e| e.name().is_none())
+            .map(|e| e.value().as_integer().and_then(|i| usize::try_from(i).ok()))
+            .collect::<Option<Vec<usize>>>()
+            .ok_or_else(|| LoadError::obj("Index list", n)),
+        None => Err(LoadError::obj("Index list", node)),
+    }
+}
+
+fn get_vecs(node: &KdlNode, key: &str) -> LoadResult<Vec<Vec3>> {
+    let floats = get_floats(node, key)?;
+    if floats.len() % 3 != 0 {
+        return Err(LoadError::new(
+            format!("{} must be a list of 3D vectors", key).as_str(),
+            node,
+        ));
+    }
+    Ok(floats.chunks(3).map(|v| vec3!(v[0], v[1], v[2])).collect())
+}
+
+fn get_vec2s(node: &KdlNode, key: &str) -> LoadResult<Vec<Vec2>> {
+    let floats = get_floats(node, key)?;
+    if floats.len() % 2 != 0 {
+        return Err(LoadError::new(
+            format!("{} must be a list of 2D vectors", key).as_str(),
+            node,
+        ));
+    }
+    Ok(floats.chunks(2).map(|v| Vec2::new(v[0], v[1])).collect())
+}
+
+fn has_child(node: &KdlNode, key: &str) -> bool {
+    node.children().is_some_and(|c| c.get(key).is_some())
+}
+
+// Reads the camera's shutter interval and curve.
+fn parse_shutter(node: &KdlNode) -> LoadResult<Shutter> {
+    let mut shutter = Shutter::default();
+    if has_child(node, "shutter_open") {
+        shutter.open = get_float(node, "shutter_open")?;
+    }
+    if has_child(node, "shutter_close") {
+        shutter.close = get_float(node, "shutter_close")?;
+    }
+    if shutter.close < shutter.open {
+        return Err(LoadError::new(
+            "shutter_close can't be before shutter_open",
+            node,
+        ));
+    }
+    if has_child(node, "shutter_curve") {
+        shutter.curve = get_string(node, "shutter_curve")?
+            .parse()
+            .map_err(|err: error::Error| LoadError::new(&err.to_string(), node))?;
+    }
+    if has_child(node, "rolling_exposure") {
+        shutter.rolling_exposure = get_float(node, "rolling_exposure")?;
+        if !(0.0..=1.0).contains(&shutter.rolling_exposure) {
+            return Err(LoadError::new(
+                "rolling_exposure must be between 0 and 1",
+                node,
+            ));
+        }
+    }
+    Ok(shutter)
+}
+
+// Reads the camera's `stereo` layout and eye settings, if any.
+fn parse_stereo(node: &KdlNode, projection: Projection) -> LoadResult<Option<Stereo>> {
+    if !has_child(node, "stereo") {
+        return Ok(None);
+    }
+    let layout = match get_string(node, "stereo")?.to_ascii_lowercase().as_str() {
+        "side-by-side" => StereoLayout::SideBySide,
+        "top-bottom" => StereoLayout::TopBottom,
+        other => {
+            return Err(LoadError::new(
+                &format!("unknown stereo layout {}", other),
+                node,
+            ))
+        }
+    };
+    if let Projection::Orthographic { .. } = projection {
+        return Err(LoadError::new(
+            "stereo needs a perspective or panoramic projection",
+            node,
+        ));
+    }
+    let interocular = get_float(node, "interocular")?;
+    let convergence = if has_child(node, "convergence") {
+        get_float(node, "convergence")?
+    } else {
+        f64::INFINITY
+    };
+    if interocular < 0.0 || convergence <= 0.0 {
+        return Err(LoadError::new(
+            "interocular can't be negative and convergence must be positive",
+            node,
+        ));
+    }
+    Ok(Some(Stereo {
+        layout,
+        interocular,
+        convergence,
+    }))
+}
+
+// Reads the camera's `projection` and the settings that go with it.
+fn parse_projection(node: &KdlNode) -> LoadResult<Projection> {
+    if !has_child(node, "projection") {
+        return Ok(Projection::default());
+    }
+    match get_string(node, "projection")?
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "perspective" => Ok(Projection::Perspective),
+        "orthographic" => {
+            let aspect_ratio =
+                get_int(node, "image_width")? as f64 / get_int(node, "image_height")? as f64;
+            let (width, height) = match (
+                has_child(node, "ortho_width"),
+                has_child(node, "ortho_height"),
+            ) {
+                (true, true) => (
+                    get_float(node, "ortho_width")?,
+                    get_float(node, "ortho_height")?,
+                ),
+                (true, false) => {
+                    let width = get_float(node, "ortho_width")?;
+                    (width, width / aspect_ratio)
+                }
+                (false, true) => {
+                    let height = get_float(node, "ortho_height")?;
+                    (height * aspect_ratio, height)
+                }
+                (false, false) => {
+                    return Err(LoadError::new(
+                        "orthographic projection needs ortho_width or ortho_height",
+                        node,
+                    ))
+                }
+            };
+            if width <= 0.0 || height <= 0.0 {
+                return Err(LoadError::new(
+                    "ortho_width and ortho_height must be positive",
+                    node,
+                ));
+            }
+            Ok(Projection::Orthographic { width, height })
+        }
+        "equirectangular" => Ok(Projection::Equirectangular),
+        "fisheye" => {
+            let mapping = if has_child(node, "fisheye_mapping") {
+                match get_string(node, "fisheye_mapping")?
+                    .to_ascii_lowercase()
+                    .as_str()
+                {
+                    "equidistant" => FisheyeMapping::Equidistant,
+                    "equisolid" => FisheyeMapping::Equisolid,
+                    other => {
+                        return Err(LoadError::new(
+                            &format!("unknown fisheye mapping {}", other),
+                            node,
+                        ))
+                    }
+                }
+            } else {
+                FisheyeMapping::default()
+            };
+            let fov = if has_child(node, "fisheye_fov") {
+                get_float(node, "fisheye_fov")?
             } else {
-                Ok(Arc::new(SolidColor(get_vec(node, key)?)))
+                180.0
+            };
+            if fov <= 0.0 || fov > 360.0 {
+                return Err(LoadError::new(
+                    "fisheye_fov must be above 0 and at most 360 degrees",
+                    node,
+                ));
+            }
+            Ok(Projection::Fisheye { mapping, fov })
+        }
+        "cubemap" => {
+            // Every eye holds the six square faces side by side.
+            let stereo = match has_child(node, "stereo") {
+                true => get_string(node, "stereo")?.to_ascii_lowercase(),
+                false => String::new(),
+            };
+            let (eyes_across, eyes_down) = match stereo.as_str() {
+                "side-by-side" => (2, 1),
+                "top-bottom" => (1, 2),
+                _ => (1, 1),
+            };
+            let width = get_int(node, "image_width")?;
+            let height = get_int(node, "image_height")?;
+            if width * eyes_down != 6 * height * eyes_across {
+                return Err(LoadError::new(
+                    &format!(
+                        "a cube map needs every eye to be 6 times as wide as it is high, \
+                         not {}x{}",
+                        width / eyes_across,
+                        height / eyes_down
+                    ),
+                    node,
+                ));
             }
+            Ok(Projection::CubeMap)
         }
-        None => Err(LoadError::obj("Checker", node)),
+        other => Err(LoadError::new(
+            &format!("unknown projection {}", other),
+            node,
+        )),
     }
 }
 
-fn parse_checker(node: &KdlNode) -> LoadResult<Checker> {
-    Ok(Checker::new(
-        get_float(node, "scale")?,
-        &parse_checker_tex(node, "even")?,
-        &parse_checker_tex(node, "odd")?,
-    ))
+// Writes `nodes` to `out` in a form that leaves out comments and formatting.
+fn write_nodes(nodes: &[KdlNode], out: &mut String) {
+    for node in nodes {
+        if let Some(ty) = node.ty() {
+            out.push_str(&format!("({:?})", ty.value()));
+        }
+        out.push_str(&format!("{:?}", node.name().value()));
+        for entry in node.entries() {
+            out.push(' ');
+            if let Some(name) = entry.name() {
+                out.push_str(&format!("{:?}=", name.value()));
+            }
+            if let Some(ty) = entry.ty() {
+                out.push_str(&format!("({:?})", ty.value()));
+            }
+            out.push_str(&format!("{:?}", entry.value()));
+        }
+        if let Some(children) = node.children() {
+            out.push('{');
+            write_nodes(children.nodes(), out);
+            out.push('}');
+        }
+        out.push(';');
+    }
+}
+
+// Hashes the scene description and the contents of the `files` it loaded,
+// leaving out the camera's sample count so more samples can be added to a
+// checkpointed render. The stratified sampler lays out its strata by the
+// sample count, so there it is kept.
+fn scene_hash(doc: &KdlDocument, files: &[PathBuf]) -> io::Result<u64> {
+    let mut doc = doc.clone();
+    if let Some(camera) = doc
+        .get_mut("Camera")
+        .and_then(|c| c.children_mut().as_mut())
+    {
+        let stratified = camera
+            .get_arg("sampler")
+            .and_then(|v| v.as_string())
+            .is_some_and(|s| matches!(s.parse(), Ok(SamplerKind::Stratified)));
+        if !stratified {
+            camera.nodes_mut().retain(|n| n.name().value() != "samples");
+        }
+    }
+    let mut text = String::new();
+    write_nodes(doc.nodes(), &mut text);
+    let mut bytes = text.into_bytes();
+    for path in files {
+        bytes.extend(fs::read(path)?);
+    }
+    Ok(fnv1a(&bytes))
+}
+
+const CAMERA_KEYS: [&str; 23] = [
+    "image_width",
+    "image_height",
+    "vfov",
+    "lookfrom",
+    "lookat",
+    "vup",
+    "defocus_angle",
+    "focus_dist",
+    "samples",
+    "max_depth",
+    "sampler",
+    "projection",
+    "ortho_width",
+    "ortho_height",
+    "fisheye_mapping",
+    "fisheye_fov",
+    "stereo",
+    "interocular",
+    "convergence",
+    "shutter_open",
+    "shutter_close",
+    "shutter_curve",
+    "rolling_exposure",
+];
+
+const FILM_KEYS: [&str; 4] = ["exposure", "tonemap", "filter", "filter_radius"];
+
+const BVH_OPTIONS: [&str; 2] = ["split", "leaf_size"];
+
+// Rejects the children of `node` that aren't among `keys`, so a misspelled
+// setting isn't silently ignored.
+fn check_keys(node: &KdlNode, keys: &[&str]) -> LoadResult {
+    for child in node.children().map(|c| c.nodes()).unwrap_or_default() {
+        if !keys.contains(&child.name().value()) {
+            return Err(LoadError::new(
+                &format!("unknown setting {}", child.name().value()),
+                child,
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Reads the build options set among the children of `node`, keeping
+// `defaults` for the rest.
+fn parse_bvh_options(node: &KdlNode, defaults: BvhOptions) -> LoadResult<BvhOptions> {
+    let mut options = defaults;
+    if has_child(node, "split") {
+        options.split = get_string(node, "split")?
+            .parse()
+            .map_err(|err: error::Error| LoadError::new(&err.to_string(), node))?;
+    }
+    if has_child(node, "leaf_size") {
+        let leaf_size = get_int(node, "leaf_size")?;
+        if leaf_size < 1 {
+            return Err(LoadError::new("leaf_size must be at least 1", node));
+        }
+        options.leaf_size = leaf_size as usize;
+    }
+    Ok(options)
+}
+
+// Composes the transform steps among the children of `node`, applied in the
+// order they are listed. Children named in `skip` aren't steps.
+fn parse_transform_steps(node: &KdlNode, skip: &[&str]) -> LoadResult<Mat4> {
+    let mut matrix = Mat4::identity();
+    for step in node.children().map(|c| c.nodes()).unwrap_or_default() {
+        if skip.contains(&step.name().value()) {
+            continue;
+        }
+        let step_matrix = match step.name().value() {
+            "translate" => Mat4::translate(&get_vec_at(step, 0)?),
+            "rotate" => {
+                let floats = node_floats(step)?;
+                match floats[..] {
+                    [angle, x, y, z] => Mat4::rotate(&vec3!(x, y, z), angle),
+                    _ => return Err(LoadError::new("rotate takes an angle and an axis", step)),
+                }
+            }
+            "rotate_x" => Mat4::rotate_x(get_float_at(step, 0)?),
+            "rotate_y" => Mat4::rotate_y(get_float_at(step, 0)?),
+            "rotate_z" => Mat4::rotate_z(get_float_at(step, 0)?),
+            "scale" => Mat4::scale(&parse_scale_factors(Some(step), node)?),
+            "matrix" => {
+                let floats = node_floats(step)?;
+                if floats.len() != 16 {
+                    return Err(LoadError::new("matrix takes 16 values, row by row", step));
+                }
+                Mat4(std::array::from_fn(|i| {
+                    std::array::from_fn(|j| floats[i * 4 + j])
+                }))
+            }
+            other => {
+                return Err(LoadError::new(
+                    format!("Unknown transform step {}", other).as_str(),
+                    step,
+                ))
+            }
+        };
+        matrix = step_matrix * matrix;
+    }
+    Ok(matrix)
 }
 
 fn parse_solid(node: &KdlNode) -> LoadResult<SolidColor> {
@@ -168,17 +698,9 @@ fn parse_solid(node: &KdlNode) -> LoadResult<SolidColor> {
     }
 }
 
-fn parse_image(node: &KdlNode) -> LoadResult<Image> {
-    if let Some(path) = node.get(1).and_then(|a| a.as_string()) {
-        Image::load(path).or_else(|err| Err(LoadError::err("Image", err, node)))
-    } else {
-        Err(LoadError::obj("Image", node))
-    }
-}
-
-fn parse_noise(node: &KdlNode) -> LoadResult<Noise> {
+fn parse_noise(node: &KdlNode, perlin: &Arc<Perlin>) -> LoadResult<Noise> {
     if let Some(expr) = node.get(1).and_then(|a| a.as_string()) {
-        Noise::parse(expr).or_else(|err| {
+        Noise::parse(expr, perlin).or_else(|err| {
             Err(LoadError::new(
                 format!("Failed to load Noise: {}", err.to_string()).as_str(),
                 node,
@@ -189,16 +711,6 @@ fn parse_noise(node: &KdlNode) -> LoadResult<Noise> {
     }
 }
 
-fn parse_tex(node: &KdlNode) -> LoadResult<Arc<dyn Texture>> {
-    match node.get(0).and_then(|a| a.as_string()) {
-        Some("Solid") => Ok(Arc::new(parse_solid(node)?)),
-        Some("Checker") => Ok(Arc::new(parse_checker(node)?)),
-        Some("Image") => Ok(Arc::new(parse_image(node)?)),
-        Some("Noise") => Ok(Arc::new(parse_noise(node)?)),
-        _ => Err(LoadError::obj("Texture", node)),
-    }
-}
-
 fn parse_gradient(node: &KdlNode) -> LoadResult<Gradient> {
     Ok(Gradient::new(
         get_vec(node, "top")?,
@@ -206,9 +718,9 @@ fn parse_gradient(node: &KdlNode) -> LoadResult<Gradient> {
     ))
 }
 
-fn parse_bg_expr(node: &KdlNode) -> LoadResult<BgExpr> {
+fn parse_bg_expr(node: &KdlNode, perlin: &Arc<Perlin>) -> LoadResult<BgExpr> {
     if let Some(expr) = node.get(1).and_then(|a| a.as_string()) {
-        Ok(BgExpr::new(expr.replace("\n", " "))?)
+        Ok(BgExpr::new(expr.replace("\n", " "), perlin)?)
     } else {
         Err(LoadError::obj("BgExpr", node))
     }
@@ -219,47 +731,108 @@ struct KdlLoader {
     doc: KdlDocument,
     textures: HashMap<String, Arc<dyn Texture>>,
     materials: HashMap<String, Arc<dyn Material>>,
+    objects: HashMap<String, Arc<dyn Object>>,
+    // The global options, or those of the enclosing `BVH` node while its
+    // objects are parsed.
+    bvh: Cell<BvhOptions>,
+    // Every file read while loading, for the scene hash.
+    files: RefCell<Vec<PathBuf>>,
+    // The noise tables for the scene seed.
+    perlin: Arc<Perlin>,
 }
 
 impl KdlLoader {
-    fn load(path: String) -> miette::Result<Scene> {
+    fn load(path: String, overrides: &[Override]) -> miette::Result<Scene> {
         let source = fs::read_to_string(&path).into_diagnostic()?;
-        let doc = KdlDocument::parse_v2(&source)?;
+        let mut doc = KdlDocument::parse_v2(&source)?;
 
-        let (camera, world, background) = Self::load_scene_parts(doc)
-            .map_err(|err| err.with_source_code(NamedSource::new(path, source)))?;
+        for o in overrides {
+            o.apply(&mut doc)?;
+        }
 
-        Scene::new(camera, world, background).into_diagnostic()
+        Self::parse_scene(doc).map_err(|err| err.with_source_code(NamedSource::new(path, source)))
     }
 
-    fn load_scene_parts(
-        doc: KdlDocument,
-    ) -> miette::Result<(Camera, Box<dyn Object>, Option<Box<dyn Background>>)> {
+    fn parse_scene(doc: KdlDocument) -> miette::Result<Scene> {
         let mut loader = KdlLoader {
             doc,
             ..Default::default()
         };
 
+        if let Some(node) = loader.doc.get("Bvh").or_else(|| loader.doc.get("BVH")) {
+            check_keys(node, &BVH_OPTIONS)?;
+            loader.bvh.set(parse_bvh_options(node, loader.bvh.get())?);
+        }
+        let seed = loader.parse_seed()?;
+        loader.perlin = Arc::new(Perlin::from_seed(seed));
         loader.load_textures()?;
         loader.load_materials()?;
+        loader.load_objects()?;
         let world = loader.parse_world()?;
         let camera = loader.parse_camera()?;
         let background = loader.parse_background()?;
+        let film = loader.parse_film()?;
+        let hash = scene_hash(&loader.doc, &loader.files.borrow()).into_diagnostic()?;
 
-        Ok((camera, world, background))
+        let mut scene = Scene::new(camera, world, background).into_diagnostic()?;
+        scene.seed = seed;
+        scene.hash = hash;
+        scene.film = film;
+        Ok(scene)
     }
 
     fn parse_world(&self) -> LoadResult<Box<dyn Object>> {
         if let Some(nodes) = self.doc.get("World").and_then(|n| n.children()) {
-            Ok(BVH::new(self.parse_objects(nodes)?))
+            Ok(BVH::build(self.parse_objects(nodes)?, &self.bvh.get()))
         } else {
             Ok(Box::new(Group::default()))
         }
     }
 
+    fn parse_checker_tex(&self, node: &KdlNode, key: &str) -> LoadResult<Arc<dyn Texture>> {
+        match node.children().and_then(|c| c.get(key)) {
+            Some(tnode) => {
+                if tnode.get(0).is_some_and(|n| n.is_string()) {
+                    self.parse_tex(&tnode)
+                } else {
+                    Ok(Arc::new(SolidColor(get_vec(node, key)?)))
+                }
+            }
+            None => Err(LoadError::obj("Checker", node)),
+        }
+    }
+
+    fn parse_checker(&self, node: &KdlNode) -> LoadResult<Checker> {
+        Ok(Checker::new(
+            get_float(node, "scale")?,
+            &self.parse_checker_tex(node, "even")?,
+            &self.parse_checker_tex(node, "odd")?,
+        ))
+    }
+
+    fn parse_image(&self, node: &KdlNode) -> LoadResult<Image> {
+        if let Some(path) = node.get(1).and_then(|a| a.as_string()) {
+            let image = Image::load(path).or_else(|err| Err(LoadError::err("Image", err, node)))?;
+            self.files.borrow_mut().push(PathBuf::from(path));
+            Ok(image)
+        } else {
+            Err(LoadError::obj("Image", node))
+        }
+    }
+
+    fn parse_tex(&self, node: &KdlNode) -> LoadResult<Arc<dyn Texture>> {
+        match node.get(0).and_then(|a| a.as_string()) {
+            Some("Solid") => Ok(Arc::new(parse_solid(node)?)),
+            Some("Checker") => Ok(Arc::new(self.parse_checker(node)?)),
+            Some("Image") => Ok(Arc::new(self.parse_image(node)?)),
+            Some("Noise") => Ok(Arc::new(parse_noise(node, &self.perlin)?)),
+            _ => Err(LoadError::obj("Texture", node)),
+        }
+    }
+
     fn get_tex(&self, node: &KdlNode) -> LoadResult<Arc<dyn Texture>> {
         match node.get(0).and_then(|a| a.as_string()) {
-            Some("Solid" | "Image" | "Checker" | "Noise") => parse_tex(node),
+            Some("Solid" | "Image" | "Checker" | "Noise") => self.parse_tex(node),
             Some(name) => self
                 .textures
                 .get(name)
@@ -358,9 +931,19 @@ impl KdlLoader {
                 {
                     Sphere::stationary(get_vec(node, "center")?, radius, &mat)
                 } else {
-                    Sphere::moving(
+                    let (time1, time2) = match (has_child(node, "time1"), has_child(node, "time2"))
+                    {
+                        (false, false) => (0.0, 1.0),
+                        _ => (get_float(node, "time1")?, get_float(node, "time2")?),
+                    };
+                    if time2 <= time1 {
+                        return Err(LoadError::new("time2 must be after time1", node));
+                    }
+                    Sphere::moving_between(
                         get_vec(node, "center1")?,
+                        time1,
                         get_vec(node, "center2")?,
+                        time2,
                         radius,
                         &mat,
                     )
@@ -371,6 +954,84 @@ impl KdlLoader {
         }
     }
 
+    fn parse_triangle(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
+        let a = get_vec(node, "a")?;
+        let b = get_vec(node, "b")?;
+        let c = get_vec(node, "c")?;
+        if let Some(n) = node.children().and_then(|c| c.get("mat")) {
+            let mat = self.get_mat(n)?;
+            Ok(Box::new(Triangle::new(a, b, c, &mat)))
+        } else {
+            Err(LoadError::obj("Triangle", node))
+        }
+    }
+
+    fn parse_mesh(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
+        let positions = get_vecs(node, "positions")?;
+        let indices = get_indices(node, "indices")?;
+        if indices.len() % 3 != 0 {
+            return Err(LoadError::new(
+                "Mesh indices must be a list of triangles",
+                node,
+            ));
+        }
+        let normals = if has_child(node, "normals") {
+            get_vecs(node, "normals")?
+        } else {
+            vec![]
+        };
+        let uvs = if has_child(node, "uvs") {
+            get_vec2s(node, "uvs")?
+        } else {
+            vec![]
+        };
+
+        let indices: Vec<[usize; 3]> = indices.chunks(3).map(|f| [f[0], f[1], f[2]]).collect();
+        let data = MeshData::indexed(positions, normals, uvs, &indices);
+        data.validate()
+            .map_err(|err| LoadError::new(format!("Invalid Mesh, {}", err).as_str(), node))?;
+
+        if let Some(n) = node.children().and_then(|c| c.get("mat")) {
+            let mat = self.get_mat(n)?;
+            Ok(Box::new(Mesh::new(data, &mat, &self.bvh.get())))
+        } else {
+            Err(LoadError::obj("Mesh", node))
+        }
+    }
+
+    fn parse_model(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
+        let Some(path) = node.get(0).and_then(|a| a.as_string()).map(Path::new) else {
+            return Err(LoadError::obj("Model", node));
+        };
+        let model = ObjModel::load(path).map_err(|err| LoadError::err("Model", err, node))?;
+        self.files.borrow_mut().push(path.to_path_buf());
+
+        let mats: Vec<Arc<dyn Material>> =
+            if let Some(n) = node.children().and_then(|c| c.get("mat")) {
+                vec![self.get_mat(n)?; model.data.faces.len()]
+            } else {
+                let library = load_model_materials(&model, path, &mut self.files.borrow_mut())
+                    .map_err(|err| LoadError::err("Model", err, node))?;
+                let default = MtlMaterial::default().to_material()?;
+                model
+                    .face_materials
+                    .iter()
+                    .map(|name| match name {
+                        Some(name) => library.get(name).map(Arc::clone).ok_or_else(|| {
+                            LoadError::new(format!("No such material {}", name).as_str(), node)
+                        }),
+                        None => Ok(Arc::clone(&default)),
+                    })
+                    .collect::<LoadResult<Vec<Arc<dyn Material>>>>()?
+            };
+
+        Ok(Box::new(Mesh::with_materials(
+            model.data,
+            &mats,
+            &self.bvh.get(),
+        )))
+    }
+
     fn parse_box(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
         let a = get_vec(node, "a")?;
         let b = get_vec(node, "b")?;
@@ -382,32 +1043,67 @@ impl KdlLoader {
         }
     }
 
-    fn parse_translate(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
-        let offset = get_vec(node, "offset")?;
-        if let Some((ty, obj)) = node
+    fn parse_inner_object(&self, node: &KdlNode, key: &str) -> LoadResult<Box<dyn Object>> {
+        match node
             .children()
-            .and_then(|c| c.get("obj"))
+            .and_then(|c| c.get(key))
             .and_then(|obj| obj.get(0).and_then(|a| a.as_string().map(|ty| (ty, obj))))
         {
-            let obj = self.parse_object(ty, obj)?;
-            Ok(Box::new(Translate::new(obj, offset)))
-        } else {
-            Err(LoadError::obj("Translate", node))
+            Some((ty, obj)) => self.parse_object(ty, obj),
+            None => Err(LoadError::obj(node.name().value(), node)),
         }
     }
 
-    fn parse_rotate_y(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
+    fn parse_translate(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
+        let offset = get_vec(node, "offset")?;
+        let obj = self.parse_inner_object(node, "obj")?;
+        Ok(Box::new(Transform::translate(obj, offset)))
+    }
+
+    fn parse_rotate(&self, node: &KdlNode, axis: Vec3) -> LoadResult<Box<dyn Object>> {
         let angle = get_float(node, "angle")?;
-        if let Some((ty, obj)) = node
-            .children()
-            .and_then(|c| c.get("obj"))
-            .and_then(|obj| obj.get(0).and_then(|a| a.as_string().map(|ty| (ty, obj))))
-        {
-            let obj = self.parse_object(ty, obj)?;
-            Ok(Box::new(RotateY::new(obj, angle)))
-        } else {
-            Err(LoadError::obj("Translate", node))
+        let obj = self.parse_inner_object(node, "obj")?;
+        Ok(Box::new(Transform::rotate(obj, axis, angle)))
+    }
+
+    fn parse_scale(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
+        let scale = parse_scale_factors(node.children().and_then(|c| c.get("scale")), node)?;
+        let obj = self.parse_inner_object(node, "obj")?;
+        Transform::new(obj, Mat4::scale(&scale))
+            .map(|t| Box::new(t) as Box<dyn Object>)
+            .ok_or_else(|| LoadError::new("Scale can't be zero", node))
+    }
+
+    // Steps are applied to the object in the order they are listed.
+    fn parse_transform(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
+        let matrix = parse_transform_steps(node, &["obj"])?;
+        let obj = self.parse_inner_object(node, "obj")?;
+        Transform::new(obj, matrix)
+            .map(|t| Box::new(t) as Box<dyn Object>)
+            .ok_or_else(|| LoadError::new("Transform can't be inverted", node))
+    }
+
+    fn parse_instance(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
+        let Some(name) = node.get(0).and_then(|a| a.as_string()) else {
+            return Err(LoadError::obj("Instance", node));
+        };
+        let obj = self
+            .objects
+            .get(name)
+            .ok_or_else(|| LoadError::new(format!("No such object {}", name).as_str(), node))?;
+        let mat = match node.children().and_then(|c| c.get("mat")) {
+            Some(n) => Some(self.get_mat(n)?),
+            None => None,
+        };
+
+        let instance = Box::new(Instance::new(obj, mat));
+        let matrix = parse_transform_steps(node, &["mat"])?;
+        if matrix == Mat4::identity() {
+            return Ok(instance);
         }
+        Transform::new(instance, matrix)
+            .map(|t| Box::new(t) as Box<dyn Object>)
+            .ok_or_else(|| LoadError::new("Instance transform can't be inverted", node))
     }
 
     fn parse_constant_medium(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
@@ -438,12 +1134,20 @@ impl KdlLoader {
     fn parse_object(&self, name: &str, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
         match name {
             "Group" => self.parse_group(node),
-            "BVH" => self.parse_bvh(node),
+            "BVH" | "Bvh" => self.parse_bvh(node),
             "Sphere" => self.parse_sphere(node),
             "Quad" => self.parse_quad(node),
+            "Triangle" => self.parse_triangle(node),
+            "Mesh" => self.parse_mesh(node),
+            "Model" => self.parse_model(node),
             "Box" => self.parse_box(node),
+            "Transform" => self.parse_transform(node),
             "Translate" => self.parse_translate(node),
-            "RotateY" => self.parse_rotate_y(node),
+            "RotateX" => self.parse_rotate(node, vec3!(1.0, 0.0, 0.0)),
+            "RotateY" => self.parse_rotate(node, vec3!(0.0, 1.0, 0.0)),
+            "RotateZ" => self.parse_rotate(node, vec3!(0.0, 0.0, 1.0)),
+            "Scale" => self.parse_scale(node),
+            "Instance" => self.parse_instance(node),
             "ConstantMedium" => self.parse_constant_medium(node),
             _ => Err(LoadError::new(
                 format!("Unknown object type {}", node.name().value()).as_str(),
@@ -462,7 +1166,17 @@ impl KdlLoader {
 
     fn parse_bvh(&self, node: &KdlNode) -> LoadResult<Box<dyn Object>> {
         if let Some(children) = node.children() {
-            Ok(BVH::new(self.parse_objects(children)?))
+            let options = parse_bvh_options(node, self.bvh.get())?;
+            // Meshes inside are built with these options too.
+            let outer = self.bvh.replace(options);
+            let objects = children
+                .nodes()
+                .iter()
+                .filter(|n| !BVH_OPTIONS.contains(&n.name().value()))
+                .map(|n| self.parse_object(n.name().value(), n))
+                .collect::<LoadResult<Vec<_>>>();
+            self.bvh.set(outer);
+            Ok(BVH::build(objects?, &options))
         } else {
             Ok(Box::new(Group::default()))
         }
@@ -477,10 +1191,18 @@ impl KdlLoader {
 
     fn parse_camera(&self) -> LoadResult<Camera> {
         if let Some(camera) = self.doc.get("Camera") {
-            Ok(Camera::new(
+            check_keys(camera, &CAMERA_KEYS)?;
+            let projection = parse_projection(camera)?;
+            // The field of view only matters for perspective.
+            let vfov = match projection {
+                Projection::Perspective => get_float(camera, "vfov")?,
+                _ if has_child(camera, "vfov") => get_float(camera, "vfov")?,
+                _ => 90.0,
+            };
+            let mut cam = Camera::new(
                 get_int(&camera, "image_width")? as usize,
                 get_int(&camera, "image_height")? as usize,
-                get_float(&camera, "vfov")?,
+                vfov,
                 get_vec(&camera, "lookfrom")?,
                 get_vec(&camera, "lookat")?,
                 get_vec(&camera, "vup")?,
@@ -488,7 +1210,16 @@ impl KdlLoader {
                 get_float(&camera, "focus_dist")?,
                 get_int(&camera, "samples")? as u32,
                 get_int(&camera, "max_depth")? as u32,
-            ))
+            )
+            .with_projection(projection)
+            .with_stereo(parse_stereo(camera, projection)?);
+            cam.shutter = parse_shutter(camera)?;
+            if has_child(camera, "sampler") {
+                cam.sampler = get_string(camera, "sampler")?
+                    .parse()
+                    .map_err(|err: error::Error| LoadError::new(&err.to_string(), camera))?;
+            }
+            Ok(cam)
         } else {
             Err(LoadError {
                 msg: "Failed to load Camera".into(),
@@ -508,12 +1239,11 @@ impl KdlLoader {
                 //     get_vec(&node, "sun_color"),
                 //     parse_gradient(node.children().unwrap().get("bg").unwrap()),
                 // )),
-                Some("Image") => {
-                    parse_image(&node).map(|x| Some(Box::new(x) as Box<dyn Background>))
-                }
-                Some("Expression") => {
-                    parse_bg_expr(&node).map(|x| Some(Box::new(x) as Box<dyn Background>))
-                }
+                Some("Image") => self
+                    .parse_image(&node)
+                    .map(|x| Some(Box::new(x) as Box<dyn Background>)),
+                Some("Expression") => parse_bg_expr(&node, &self.perlin)
+                    .map(|x| Some(Box::new(x) as Box<dyn Background>)),
                 Some(ty) => Err(LoadError::new(
                     format!("unknown background type {}", ty).as_str(),
                     node,
@@ -525,6 +1255,45 @@ impl KdlLoader {
         }
     }
 
+    fn parse_seed(&self) -> LoadResult<u64> {
+        match self.doc.get("Seed") {
+            Some(node) => node
+                .get(0)
+                .and_then(|a| a.as_integer())
+                .and_then(|seed| u64::try_from(seed).ok())
+                .ok_or_else(|| LoadError::obj("Seed", node)),
+            None => Ok(0),
+        }
+    }
+
+    fn parse_film(&self) -> LoadResult<Film> {
+        let mut film = Film::default();
+        if let Some(node) = self.doc.get("Film") {
+            check_keys(node, &FILM_KEYS)?;
+            if has_child(node, "exposure") {
+                film.exposure = get_float(node, "exposure")?;
+            }
+            if has_child(node, "tonemap") {
+                film.tonemap = get_string(node, "tonemap")?
+                    .parse()
+                    .map_err(|err: error::Error| LoadError::new(&err.to_string(), node))?;
+            }
+            if has_child(node, "filter") {
+                let kind = get_string(node, "filter")?
+                    .parse()
+                    .map_err(|err: error::Error| LoadError::new(&err.to_string(), node))?;
+                film.filter = Filter::new(kind);
+            }
+            if has_child(node, "filter_radius") {
+                film.filter.radius = get_float(node, "filter_radius")?;
+                if film.filter.radius <= 0.0 {
+                    return Err(LoadError::new("filter_radius must be positive", node));
+                }
+            }
+        }
+        Ok(film)
+    }
+
     fn load_textures(&mut self) -> LoadResult {
         if let Some(nodes) = self
             .doc
@@ -532,13 +1301,14 @@ impl KdlLoader {
             .and_then(|t| t.children())
             .map(|c| c.nodes())
         {
-            self.textures = nodes
+            let textures = nodes
                 .iter()
                 .map(|tnode| {
                     let name = tnode.name().value().to_string();
-                    parse_tex(tnode).map(|tex| (name, tex))
+                    self.parse_tex(tnode).map(|tex| (name, tex))
                 })
                 .collect::<LoadResult<HashMap<String, Arc<dyn Texture>>>>()?;
+            self.textures = textures;
         }
         Ok(())
     }
@@ -560,8 +1330,473 @@ impl KdlLoader {
         }
         Ok(())
     }
+
+    // Each entry is built once with its own BVH. Entries can instance the
+    // ones listed before them.
+    fn load_objects(&mut self) -> LoadResult {
+        let Some(nodes) = self.doc.get("Objects").and_then(|o| o.children()) else {
+            return Ok(());
+        };
+        for onode in nodes.nodes() {
+            let obj: Arc<dyn Object> = match onode.children() {
+                Some(children) => {
+                    Arc::from(BVH::build(self.parse_objects(children)?, &self.bvh.get()))
+                }
+                None => Arc::new(Group::default()),
+            };
+            self.objects.insert(onode.name().value().to_string(), obj);
+        }
+        Ok(())
+    }
 }
 
+/// Loads a scene from a KDL file.
 pub fn load_scene(path: String) -> miette::Result<Scene> {
-    KdlLoader::load(path)
+    KdlLoader::load(path, &[])
+}
+
+/// Loads a scene from a KDL file after applying `overrides` to the document.
+pub fn load_scene_with(path: String, overrides: &[Override]) -> miette::Result<Scene> {
+    KdlLoader::load(path, overrides)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use crate::{
+        bvh::SplitMethod, interval::Interval, math::Point3, point, ray::Ray, rng::Rng,
+        shutter::ShutterCurve, stats,
+    };
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_override() {
+        let o: Override = "camera.vfov=30".parse().unwrap();
+        assert_eq!(o, Override::new(&["camera", "vfov"], "30".into()));
+        assert!("camera.vfov".parse::<Override>().is_err());
+        assert!("=30".parse::<Override>().is_err());
+    }
+
+    #[test]
+    fn test_apply_override() {
+        let mut doc = KdlDocument::parse_v2(
+            "Camera {\n  vfov 40.0\n  samples 100\n  lookfrom 0.0 0.0 0.0\n}",
+        )
+        .unwrap();
+
+        for o in [
+            "camera.vfov=30",
+            "Camera.samples=8",
+            "camera.lookfrom=1 2 3",
+            "Seed=7",
+        ] {
+            o.parse::<Override>().unwrap().apply(&mut doc).unwrap();
+        }
+
+        let camera = doc.get("Camera").unwrap();
+        assert_eq!(get_float(camera, "vfov").unwrap(), 30.0);
+        assert_eq!(get_int(camera, "samples").unwrap(), 8);
+        assert_eq!(get_vec(camera, "lookfrom").unwrap(), vec3!(1.0, 2.0, 3.0));
+        assert_eq!(
+            doc.get("Seed").and_then(|n| n.get(0)),
+            Some(&KdlValue::Integer(7))
+        );
+    }
+
+    #[test]
+    fn test_apply_override_type_mismatch() {
+        let mut doc = KdlDocument::parse_v2("Camera {\n  samples 100\n}").unwrap();
+        let o: Override = "camera.samples=lots".parse().unwrap();
+        assert!(o.apply(&mut doc).is_err());
+    }
+
+    #[test]
+    fn test_apply_override_unknown_key() {
+        let mut doc = KdlDocument::parse_v2("Camera {\n  vfov 40.0\n}").unwrap();
+        for o in ["camera.vfvo=30", "Camera.sampels=8", "Camra.vfov=30"] {
+            let err = o.parse::<Override>().unwrap().apply(&mut doc).unwrap_err();
+            assert!(err.msg.contains(o.split_once('=').unwrap().0));
+        }
+        assert_eq!(doc.to_string(), "Camera {\n  vfov 40.0\n}");
+
+        // Keys the loader reads can be added.
+        for o in ["Camera.max_depth=3", "film.exposure=1.5", "BVH.leaf_size=2"] {
+            o.parse::<Override>().unwrap().apply(&mut doc).unwrap();
+        }
+        assert_eq!(get_int(doc.get("Camera").unwrap(), "max_depth").unwrap(), 3);
+        assert_eq!(
+            get_float(doc.get("Film").unwrap(), "exposure").unwrap(),
+            1.5
+        );
+        assert_eq!(get_int(doc.get("Bvh").unwrap(), "leaf_size").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_parse_film() {
+        let loader = KdlLoader {
+            doc: KdlDocument::parse_v2("Film {\n  exposure -1.5\n  tonemap \"agx\"\n}").unwrap(),
+            ..Default::default()
+        };
+        let film = loader.parse_film().unwrap();
+        assert_eq!(film.exposure, -1.5);
+        assert_eq!(film.tonemap, crate::film::Tonemap::Agx);
+        assert_eq!(film.filter, Filter::default());
+
+        let loader = KdlLoader {
+            doc: KdlDocument::parse_v2("Film {\n  filter \"gaussian\"\n  filter_radius 2.0\n}")
+                .unwrap(),
+            ..Default::default()
+        };
+        let filter = loader.parse_film().unwrap().filter;
+        assert_eq!(filter.kind, crate::filter::FilterKind::Gaussian);
+        assert_eq!(filter.radius, 2.0);
+
+        let loader = KdlLoader::default();
+        assert_eq!(loader.parse_film().unwrap(), Film::default());
+
+        let loader = KdlLoader {
+            doc: KdlDocument::parse_v2("Film {\n  tonemap \"filmic\"\n}").unwrap(),
+            ..Default::default()
+        };
+        assert!(loader.parse_film().is_err());
+    }
+
+    // Parses a 4x2 camera with the required settings and `extra` ones.
+    fn camera(extra: &str) -> LoadResult<Camera> {
+        camera_sized(4, 2, extra)
+    }
+
+    fn camera_sized(width: usize, height: usize, extra: &str) -> LoadResult<Camera> {
+        let loader = KdlLoader {
+            doc: KdlDocument::parse_v2(&format!(
+                "Camera {{ image_width {}; image_height {}; vfov 40.0; lookfrom 0.0 0.0 0.0; \
+                 lookat 0.0 0.0 -1.0; vup 0.0 1.0 0.0; defocus_angle 0.0; focus_dist 1.0; \
+                 samples 16; max_depth 8; {} }}",
+                width, height, extra
+            ))
+            .unwrap(),
+            ..Default::default()
+        };
+        loader.parse_camera()
+    }
+
+    #[test]
+    fn test_unknown_setting() {
+        assert!(camera("vfvo 30.0").is_err());
+        let loader = KdlLoader {
+            doc: KdlDocument::parse_v2("Film { exposre 1.0; }").unwrap(),
+            ..Default::default()
+        };
+        assert!(loader.parse_film().is_err());
+
+        // Every key an override may add is one the parsers read. The first
+        // ten are the ones `camera_sized` sets.
+        let all = "sampler stratified; projection cubemap; ortho_width 1.0; ortho_height 1.0; \
+                   fisheye_mapping equisolid; fisheye_fov 180.0; stereo top-bottom; \
+                   interocular 0.065; convergence 2.0; shutter_open 0.0; shutter_close 1.0; \
+                   shutter_curve box; rolling_exposure 0.5";
+        let doc = KdlDocument::parse_v2(&format!("Camera {{ {} }}", all)).unwrap();
+        let keys: Vec<&str> = doc.nodes()[0]
+            .iter_children()
+            .map(|n| n.name().value())
+            .collect();
+        assert!(CAMERA_KEYS.iter().skip(10).all(|k| keys.contains(k)));
+        assert!(camera_sized(6, 2, all).is_ok());
+        let loader = KdlLoader {
+            doc: KdlDocument::parse_v2(
+                "Film { exposure 1.0; tonemap aces; filter gaussian; filter_radius 1.5; }",
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        assert!(loader.parse_film().is_ok());
+    }
+
+    #[test]
+    fn test_parse_sampler() {
+        assert_eq!(camera("").unwrap().sampler, SamplerKind::Independent);
+        assert_eq!(camera("sampler sobol").unwrap().sampler, SamplerKind::Sobol);
+        assert!(camera("sampler blue").is_err());
+    }
+
+    #[test]
+    fn test_parse_projection() {
+        assert_eq!(camera("").unwrap().projection(), Projection::Perspective);
+        assert_eq!(
+            camera("projection orthographic; ortho_width 10.0")
+                .unwrap()
+                .projection(),
+            Projection::Orthographic {
+                width: 10.0,
+                height: 5.0
+            }
+        );
+        assert_eq!(
+            camera("projection orthographic; ortho_height 3.0")
+                .unwrap()
+                .projection(),
+            Projection::Orthographic {
+                width: 6.0,
+                height: 3.0
+            }
+        );
+        assert!(camera("projection orthographic").is_err());
+        assert!(camera("projection oblique").is_err());
+
+        assert_eq!(
+            camera("projection equirectangular").unwrap().projection(),
+            Projection::Equirectangular
+        );
+        assert_eq!(
+            camera("projection fisheye").unwrap().projection(),
+            Projection::Fisheye {
+                mapping: FisheyeMapping::Equidistant,
+                fov: 180.0
+            }
+        );
+        assert_eq!(
+            camera("projection Fisheye; fisheye_mapping Equisolid; fisheye_fov 220.0")
+                .unwrap()
+                .projection(),
+            Projection::Fisheye {
+                mapping: FisheyeMapping::Equisolid,
+                fov: 220.0
+            }
+        );
+        assert!(camera("projection fisheye; fisheye_mapping stereographic").is_err());
+        assert!(camera("projection fisheye; fisheye_fov 400.0").is_err());
+        assert_eq!(
+            camera_sized(12, 2, "projection cubemap")
+                .unwrap()
+                .projection(),
+            Projection::CubeMap
+        );
+        assert!(camera("projection cubemap").is_err());
+        assert!(camera_sized(12, 3, "projection cubemap").is_err());
+        let stereo = "projection cubemap; interocular 0.065; stereo";
+        assert!(camera_sized(24, 2, &format!("{} side-by-side", stereo)).is_ok());
+        assert!(camera_sized(12, 2, &format!("{} side-by-side", stereo)).is_err());
+        assert!(camera_sized(6, 2, &format!("{} top-bottom", stereo)).is_ok());
+        assert!(camera_sized(12, 2, &format!("{} top-bottom", stereo)).is_err());
+    }
+
+    #[test]
+    fn test_noise_follows_seed() {
+        let noise = |seed: u64| {
+            let doc = KdlDocument::parse_v2(&format!(
+                "Seed {}\nBackground Expression \"noise(d * 3.0)\"\n\
+                 Camera {{ image_width 4; image_height 2; vfov 40.0; lookfrom 0.0 0.0 0.0; \
+                 lookat 0.0 0.0 -1.0; vup 0.0 1.0 0.0; defocus_angle 0.0; focus_dist 1.0; \
+                 samples 16; max_depth 8; }}",
+                seed
+            ))
+            .unwrap();
+            let scene = KdlLoader::parse_scene(doc).unwrap();
+            scene.background.sample_bg(&vec3!(0.3, 0.2, 0.1))
+        };
+        assert_eq!(noise(1), noise(1));
+        assert_ne!(noise(1), noise(2));
+    }
+
+    #[test]
+    fn test_parse_shutter() {
+        assert_eq!(camera("").unwrap().shutter, Shutter::default());
+        assert_eq!(
+            camera("shutter_open 0.25; shutter_close 0.75; shutter_curve rolling; rolling_exposure 0.1")
+                .unwrap()
+                .shutter,
+            Shutter {
+                open: 0.25,
+                close: 0.75,
+                curve: ShutterCurve::Rolling,
+                rolling_exposure: 0.1
+            }
+        );
+        assert!(camera("shutter_open 1.0; shutter_close 0.5").is_err());
+        assert!(camera("shutter_curve iris").is_err());
+        assert!(camera("shutter_curve rolling; rolling_exposure 2.0").is_err());
+    }
+
+    #[test]
+    fn test_parse_moving_sphere() {
+        let loader = KdlLoader::default();
+        let doc = KdlDocument::parse_v2(
+            "Sphere {\n  center1 0.0 0.0 0.0\n  center2 2.0 0.0 0.0\n  time1 1.0\n  time2 3.0\n  radius 0.5\n  mat \"Lambertian\" { albedo 0.5 0.5 0.5; }\n}\nSphere {\n  center1 0.0 0.0 0.0\n  center2 2.0 0.0 0.0\n  time1 1.0\n  time2 1.0\n  radius 0.5\n  mat \"Lambertian\" { albedo 0.5 0.5 0.5; }\n}",
+        )
+        .unwrap();
+        let nodes = doc.nodes();
+
+        // Halfway along at time 2.
+        let obj = loader.parse_object("Sphere", &nodes[0]).unwrap();
+        let r = Ray::new(point!(1.0, 0.0, 5.0), vec3!(0.0, 0.0, -1.0), 2.0);
+        let hit = obj.hit(&r, &Interval::from(0.001), &mut Rng::seed_from_u64(0));
+        assert!(hit.is_some_and(|hit| (hit.t - 4.5).abs() < 1e-9));
+
+        assert!(loader.parse_object("Sphere", &nodes[1]).is_err());
+    }
+
+    #[test]
+    fn test_parse_stereo() {
+        assert_eq!(camera("").unwrap().stereo(), None);
+        assert_eq!(
+            camera("stereo side-by-side; interocular 0.065")
+                .unwrap()
+                .stereo(),
+            Some(Stereo {
+                layout: StereoLayout::SideBySide,
+                interocular: 0.065,
+                convergence: f64::INFINITY
+            })
+        );
+        assert_eq!(
+            camera(
+                "projection Equirectangular; stereo Top-Bottom; interocular 0.065; convergence 2.0"
+            )
+            .unwrap()
+            .stereo(),
+            Some(Stereo {
+                layout: StereoLayout::TopBottom,
+                interocular: 0.065,
+                convergence: 2.0
+            })
+        );
+        assert!(camera("stereo side-by-side").is_err());
+        assert!(camera("stereo anaglyph; interocular 0.065").is_err());
+        assert!(camera("stereo side-by-side; interocular 0.065; convergence 0.0").is_err());
+        assert!(camera(
+            "projection orthographic; ortho_width 10.0; stereo top-bottom; interocular 0.065"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_transform() {
+        let loader = KdlLoader::default();
+        let doc = KdlDocument::parse_v2(
+            "Transform {\n  scale 2.0\n  rotate 90.0 0.0 0.0 1.0\n  translate 0.0 0.0 -5.0\n  obj Sphere {\n    center 1.0 0.0 0.0\n    radius 0.5\n    mat \"Lambertian\" { albedo 0.5 0.5 0.5; }\n  }\n}\nTransform {\n  scale 0.0 1.0 1.0\n  obj Box { a 0.0 0.0 0.0; b 1.0 1.0 1.0; mat \"Lambertian\" { albedo 0.5 0.5 0.5; }; }\n}",
+        )
+        .unwrap();
+        let nodes = doc.nodes();
+
+        // The sphere ends up centered on (0, 2, -5) with radius 1.
+        let obj = loader.parse_object("Transform", &nodes[0]).unwrap();
+        let bbox = obj.bbox();
+        assert!((bbox.x.min + 1.0).abs() < 1e-3 && (bbox.x.max - 1.0).abs() < 1e-3);
+        assert!((bbox.y.min - 1.0).abs() < 1e-3 && (bbox.y.max - 3.0).abs() < 1e-3);
+        assert!((bbox.z.min + 6.0).abs() < 1e-3 && (bbox.z.max + 4.0).abs() < 1e-3);
+
+        assert!(loader.parse_object("Transform", &nodes[1]).is_err());
+    }
+
+    #[test]
+    fn test_scene_hash() {
+        let hash = |src: &str| scene_hash(&KdlDocument::parse_v2(src).unwrap(), &[]).unwrap();
+        let base = hash("Camera { vfov 40.0; samples 100; }\nSeed 1");
+        assert_eq!(base, hash("Camera { vfov 40.0; samples 500; }\nSeed 1"));
+        assert_ne!(base, hash("Camera { vfov 45.0; samples 100; }\nSeed 1"));
+        // The stratified sampler's strata depend on the sample count.
+        assert_ne!(
+            hash("Camera { sampler stratified; samples 100; }"),
+            hash("Camera { sampler stratified; samples 500; }")
+        );
+        assert_eq!(
+            hash("Camera { sampler sobol; samples 100; }"),
+            hash("Camera { sampler sobol; samples 500; }")
+        );
+        assert_eq!(
+            base,
+            hash("// The camera\nCamera {\n    vfov 40.00\n    samples 100\n}\nSeed 1 /* fixed */")
+        );
+
+        // The contents of the files loaded count too.
+        let doc = KdlDocument::parse_v2("Seed 1").unwrap();
+        let path = env::temp_dir().join(format!("yarr-scene-hash-{}.mtl", process::id()));
+        fs::write(&path, "newmtl red\nKd 0.8 0.1 0.1\n").unwrap();
+        let before = scene_hash(&doc, std::slice::from_ref(&path)).unwrap();
+        assert_ne!(before, hash("Seed 1"));
+        fs::write(&path, "newmtl red\nKd 0.9 0.1 0.1\n").unwrap();
+        let after = scene_hash(&doc, std::slice::from_ref(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_ne!(before, after);
+    }
+
+    #[test]
+    fn test_parse_bvh_options() {
+        let doc = KdlDocument::parse_v2(
+            "Bvh { split sah; }\nBVH { leaf_size 4; Sphere { center 0.0 0.0 0.0; radius 1.0; mat Lambertian { albedo 0.5 0.5 0.5; }; } }\nBvh { split middle; }\nBvh { leaf_size 0; }",
+        )
+        .unwrap();
+        let nodes = doc.nodes();
+
+        let options = parse_bvh_options(&nodes[0], BvhOptions::default()).unwrap();
+        assert_eq!(options.split, SplitMethod::Sah);
+        assert_eq!(options.leaf_size, 1);
+        let options = parse_bvh_options(&nodes[1], options).unwrap();
+        assert_eq!(options.split, SplitMethod::Sah);
+        assert_eq!(options.leaf_size, 4);
+        assert!(KdlLoader::default().parse_object("BVH", &nodes[1]).is_ok());
+
+        assert!(parse_bvh_options(&nodes[2], options).is_err());
+        assert!(parse_bvh_options(&nodes[3], options).is_err());
+    }
+
+    #[test]
+    fn test_mesh_bvh_options() {
+        let mesh = "Mesh { positions 0.0 0.0 0.0 1.0 0.0 0.0 2.0 0.0 0.0 0.0 1.0 0.0 1.0 1.0 0.0 2.0 1.0 0.0; indices 0 1 4 0 4 3 1 2 5 1 5 4; mat Lambertian { albedo 0.5 0.5 0.5; }; }";
+        let doc =
+            KdlDocument::parse_v2(&format!("{}\nBvh {{ leaf_size 8; {} }}", mesh, mesh)).unwrap();
+        let nodes = doc.nodes();
+        let r = Ray::new(point!(0.25, 0.5, 5.0), vec3!(0.0, 0.0, -1.0), 0.0);
+        let nodes_visited = |obj: Box<dyn Object>| {
+            stats::take();
+            assert!(obj
+                .hit(&r, &Interval::from(0.001), &mut Rng::seed_from_u64(0))
+                .is_some());
+            stats::take().bvh_nodes
+        };
+
+        let loader = KdlLoader::default();
+        let obj = loader.parse_object("Mesh", &nodes[0]).unwrap();
+        assert!(nodes_visited(obj) > 1);
+        // A single leaf holds all the faces.
+        let obj = loader.parse_object("Bvh", &nodes[1]).unwrap();
+        assert_eq!(nodes_visited(obj), 1);
+        assert_eq!(loader.bvh.get(), BvhOptions::default());
+    }
+
+    #[test]
+    fn test_parse_instance() {
+        let doc = KdlDocument::parse_v2(
+            "Materials {\n  red Lambertian { albedo 1.0 0.0 0.0; }\n}\nObjects {\n  ball {\n    Sphere { center 0.0 0.0 0.0; radius 1.0; mat Lambertian { albedo 0.5 0.5 0.5; }; }\n  }\n  pair {\n    Instance ball { translate -2.0 0.0 0.0; }\n    Instance ball { translate 2.0 0.0 0.0; }\n  }\n}\nInstance pair { mat red; translate 0.0 0.0 -5.0; }\nInstance nothing",
+        )
+        .unwrap();
+        let mut loader = KdlLoader {
+            doc,
+            ..Default::default()
+        };
+        loader.load_materials().unwrap();
+        loader.load_objects().unwrap();
+        let nodes = loader.doc.nodes();
+
+        let obj = loader.parse_object("Instance", &nodes[2]).unwrap();
+        let bbox = obj.bbox();
+        assert!((bbox.x.min + 3.0).abs() < 1e-3 && (bbox.x.max - 3.0).abs() < 1e-3);
+        assert!((bbox.z.min + 6.0).abs() < 1e-3 && (bbox.z.max + 4.0).abs() < 1e-3);
+
+        let r = Ray::new(point!(2.0, 0.0, 0.0), vec3!(0.0, 0.0, -1.0), 0.0);
+        let hit = obj
+            .hit(&r, &Interval::from(0.001), &mut Rng::seed_from_u64(0))
+            .unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-9);
+        let scattered = hit
+            .mat
+            .scatter(&r, &hit, &mut Rng::seed_from_u64(0))
+            .unwrap();
+        assert_eq!(scattered.att, rgb!(1.0, 0.0, 0.0));
+
+        assert!(loader.parse_object("Instance", &nodes[3]).is_err());
+    }
 }
@@ -1,53 +1,134 @@
-use std::env;
-
-use thread_pool::{render_threaded, render_unthreaded};
-
-use crate::loader::load_scene;
-
-mod aabb;
-mod background;
-mod bvh;
-mod camera;
-mod checker;
-mod color;
-mod constant_medium;
-mod dielectric;
-mod diffuse_light;
-mod error;
-mod expression;
-mod group;
-mod image;
-mod interval;
-mod lambertian;
-mod loader;
-mod material;
-mod math;
-mod metal;
-mod object;
-mod perlin;
-mod quad;
-mod ray;
-mod scene;
-mod shapes;
-mod solid_color;
-mod sphere;
-mod test_data;
-mod texture;
-mod thread_pool;
-mod transform;
-mod util;
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: yarr <path_to_file.kdl>");
-        return;
-    }
-
-    let scene = load_scene(args[1].clone()).unwrap();
-
-    let cpus = num_cpus::get();
-
-    render_threaded(cpus, &scene);
-    // render_unthreaded(&scene);
+use std::{env, fs, time::Instant};
+
+use cli::{Args, USAGE};
+use miette::IntoDiagnostic;
+use yarr_tracer::{
+    load_scene_with, render_progressive, render_with_stats, stats, stats::Counters, write_image,
+    Accumulation, Checkpoint, Film, FrameBuffer, OutputFormat, ProgressReporter,
+    ProgressiveOptions, QuietProgress, RenderOptions, RenderStats, Scene, TerminalProgress,
+};
+
+mod cli;
+
+fn main() -> miette::Result<()> {
+    let args =
+        Args::parse(env::args().skip(1)).map_err(|err| miette::miette!("{}\n\n{}", err, USAGE))?;
+    if args.help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let format = match args.format {
+        Some(format) => format,
+        None => OutputFormat::from_path(&args.output).into_diagnostic()?,
+    };
+
+    let start = Instant::now();
+    let scene = load_scene_with(args.scene.clone(), &args.overrides)?;
+    let load = start.elapsed();
+    let load_counters = stats::take();
+
+    let mut options = RenderOptions::default();
+    if args.single_threaded {
+        options.threads = 1;
+    } else if let Some(threads) = args.threads {
+        options.threads = threads;
+    }
+    if let Some(tile_size) = args.tile_size {
+        options.tile_size = tile_size;
+    }
+    if let Some(tile_order) = args.tile_order {
+        options.tile_order = tile_order;
+    }
+
+    let progress: Box<dyn ProgressReporter> = if args.quiet {
+        Box::new(QuietProgress)
+    } else {
+        Box::new(TerminalProgress::default())
+    };
+
+    let render_start = Instant::now();
+    let (image, mut counters) = match args.progressive_options() {
+        Some(progressive) => render_in_passes(
+            &args,
+            &scene,
+            &options,
+            &progressive,
+            progress.as_ref(),
+            format,
+        )?,
+        None => render_with_stats(&scene, &options, progress.as_ref()),
+    };
+    let render = render_start.elapsed();
+    counters += load_counters;
+
+    write_image(&image, &scene.film, &args.output, format).into_diagnostic()?;
+
+    let stats = RenderStats {
+        wall: start.elapsed(),
+        load,
+        render,
+        threads: options.threads.max(1),
+        pixels: image.width * image.height,
+        counters,
+    };
+    if !args.quiet {
+        eprintln!("{}", stats);
+    }
+    if let Some(path) = args.stats_json {
+        fs::write(path, stats.to_json()).into_diagnostic()?;
+    }
+    Ok(())
+}
+
+// Renders progressively, writing snapshots of the image and checkpoints as
+// `args` ask for.
+fn render_in_passes(
+    args: &Args,
+    scene: &Scene,
+    options: &RenderOptions,
+    progressive: &ProgressiveOptions,
+    progress: &dyn ProgressReporter,
+    format: OutputFormat,
+) -> miette::Result<(FrameBuffer, Counters)> {
+    let acc = match &args.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::read(path).into_diagnostic()?;
+            checkpoint.validate(scene).into_diagnostic()?;
+            checkpoint.acc
+        }
+        None => Accumulation::new(scene.camera.image_width, scene.camera.image_height),
+    };
+    let checkpoint = args.checkpoint_path();
+
+    let result = render_progressive(scene, options, progressive, acc, progress, |acc| {
+        write_image(&acc.average(), &scene.film, &args.output, format)?;
+        match checkpoint {
+            Some(path) => Checkpoint::save(scene, acc, path),
+            None => Ok(()),
+        }
+    })
+    .into_diagnostic()?;
+
+    if let Some(path) = checkpoint {
+        Checkpoint::save(scene, &result.acc, path).into_diagnostic()?;
+    }
+    if let Some(path) = &args.sample_heatmap {
+        let heatmap = result.acc.sample_heatmap();
+        let format = OutputFormat::from_path(path).into_diagnostic()?;
+        write_image(&heatmap, &Film::default(), path, format).into_diagnostic()?;
+    }
+    if !args.quiet {
+        if result.out_of_time {
+            eprintln!("Stopped at {} samples per pixel", result.samples());
+        }
+        if progressive.adaptive.is_some() {
+            eprintln!(
+                "Used {:.1} samples per pixel on average, {} at least",
+                result.mean_samples(),
+                result.samples()
+            );
+        }
+    }
+    Ok((result.acc.average(), result.counters))
 }
@@ -1,16 +1,63 @@
-use crate::{color::Color, object::Hit, ray::Ray, rgb};
+use crate::{color::Color, object::Hit, ray::Ray, rgb, rng::Rng};
 
+/// A scattered ray and the attenuation applied to the light it carries.
+///
+/// For non-specular scatters `att` is already divided by `pdf`, i.e. it is
+/// [`Material::eval`] over [`Material::pdf`] for the sampled direction.
 pub struct Scatter {
     pub att: Color,
     pub ray: Ray,
+    /// The solid angle density with which `ray` was picked. Meaningless for
+    /// specular scatters.
+    pub pdf: f64,
+    /// Whether `ray` was picked from a delta lobe, such as a mirror, which
+    /// can't be evaluated for arbitrary directions.
+    pub specular: bool,
 }
 
+impl Scatter {
+    pub fn diffuse(att: Color, ray: Ray, pdf: f64) -> Self {
+        Self {
+            att,
+            ray,
+            pdf,
+            specular: false,
+        }
+    }
+
+    pub fn specular(att: Color, ray: Ray) -> Self {
+        Self {
+            att,
+            ray,
+            pdf: 0.0,
+            specular: true,
+        }
+    }
+}
+
+/// Describes how a surface scatters and emits light.
 pub trait Material: Send + Sync {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<Scatter> {
+    fn scatter(&self, _r_in: &Ray, _hit: &Hit, _rng: &mut Rng) -> Option<Scatter> {
         None
     }
 
-    fn emitted(&self, r_in: &Ray, hit: &Hit) -> Color {
+    fn emitted(&self, _r_in: &Ray, _hit: &Hit) -> Color {
         rgb!(0.0, 0.0, 0.0)
     }
+
+    /// The BSDF times the cosine term for light arriving along `scattered` and
+    /// leaving along `r_in`. Zero for delta lobes.
+    fn eval(&self, _r_in: &Ray, _hit: &Hit, _scattered: &Ray) -> Color {
+        rgb!(0.0)
+    }
+
+    /// The density with which [`Material::scatter`] picks `scattered`. Zero for
+    /// delta lobes, which can't be combined with light sampling.
+    fn pdf(&self, _r_in: &Ray, _hit: &Hit, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
 }
@@ -1,15 +1,18 @@
-use std::{cmp::Ordering, fmt, ops};
+use std::{cmp::Ordering, f64::consts::PI, fmt, ops};
 
-use rand::random;
+use rand::Rng as _;
 
-use crate::util::{random_in_range, EPSILON};
+use crate::{
+    rng::Rng,
+    util::{random_in_range, EPSILON},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Vector<const N: usize>(pub [f64; N]);
 
-fn gen_arr<const N: usize, T: Default + Copy, F>(gen: F) -> [T; N]
+fn gen_arr<const N: usize, T: Default + Copy, F>(mut gen: F) -> [T; N]
 where
-    F: Fn(usize) -> T,
+    F: FnMut(usize) -> T,
 {
     let mut arr = [T::default(); N];
     for i in 0..N {
@@ -38,12 +41,12 @@ impl<const N: usize> Vector<N> {
         Self([t; N])
     }
 
-    pub fn random() -> Self {
-        Self(arr!(N, random()))
+    pub fn random(rng: &mut Rng) -> Self {
+        Self(arr!(N, rng.random()))
     }
 
-    pub fn random_in_range(min: f64, max: f64) -> Self {
-        Self(arr!(N, random_in_range(min, max)))
+    pub fn random_in_range(rng: &mut Rng, min: f64, max: f64) -> Self {
+        Self(arr!(N, random_in_range(rng, min, max)))
     }
 
     pub fn negate(&mut self) -> &Self {
@@ -125,10 +128,6 @@ impl<const N: usize> Vector<N> {
     pub fn smoothed(&self) -> Self {
         Vector::<N>(self.0.map(|e| e * e * (3.0 - 2.0 * e)))
     }
-
-    pub(crate) fn abs(&self) -> Self {
-        Vector::<N>(self.0.map(|e| e.abs()))
-    }
 }
 
 impl<const N: usize> PartialEq for Vector<N> {
@@ -351,20 +350,25 @@ impl Vec3 {
         Self([x, y, z])
     }
 
-    pub fn random_in_unit_sphere() -> Self {
+    pub fn random_in_unit_sphere(rng: &mut Rng) -> Self {
         loop {
-            let p = Vec3::random_in_range(-1.0, 1.0);
+            let p = Vec3::random_in_range(rng, -1.0, 1.0);
             let lensq = p.length_squared();
             if 1e-160 < lensq && lensq < 1.0 {
                 break p;
             };
         }
     }
-    pub fn random_unit() -> Self {
-        Vec3::random_in_unit_sphere().unit()
+    /// A uniformly distributed unit vector.
+    pub fn random_unit(rng: &mut Rng) -> Self {
+        let (r1, r2) = rng.get_2d();
+        let z = 1.0 - 2.0 * r1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * r2;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
     }
-    pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
-        let on_unit_sphere = Vec3::random_unit();
+    pub fn random_on_hemisphere(rng: &mut Rng, normal: &Vec3) -> Vec3 {
+        let on_unit_sphere = Vec3::random_unit(rng);
         if on_unit_sphere.dot(normal) > 0.0 {
             // In the same hemisphere as the normal
             on_unit_sphere
@@ -372,13 +376,32 @@ impl Vec3 {
             -on_unit_sphere
         }
     }
-    pub fn random_in_unit_disk() -> Self {
-        loop {
-            let p = Vec3::new(random_in_range(-1.0, 1.0), random_in_range(-1.0, 1.0), 0.0);
-            if p.length_squared() < 1.0 {
-                break p;
-            };
+    /// A random direction around the z axis, distributed by the cosine of its
+    /// angle to the axis.
+    pub fn random_cosine_direction(rng: &mut Rng) -> Self {
+        let (r1, r2) = rng.get_2d();
+
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+
+        Vec3::new(x, y, z)
+    }
+    /// A uniformly distributed point in the unit disk in the xy plane, using
+    /// Shirley and Chiu's concentric mapping so nearby samples stay nearby.
+    pub fn random_in_unit_disk(rng: &mut Rng) -> Self {
+        let (r1, r2) = rng.get_2d();
+        let (a, b) = (2.0 * r1 - 1.0, 2.0 * r2 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
         }
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, PI / 4.0 * (b / a))
+        } else {
+            (b, PI / 2.0 - PI / 4.0 * (a / b))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
     }
 
     pub fn x(&self) -> f64 {
@@ -454,14 +477,16 @@ impl Vec2 {
         self.0[1]
     }
 
-    pub fn sample_square() -> Self {
+    pub fn sample_square(rng: &mut Rng) -> Self {
         // Returns the vector to a random point in the [-.5,-.5]-[+.5,+.5] unit square.
-        Vec2::new(random::<f64>() - 0.5, random::<f64>() - 0.5)
+        Vec2::new(rng.random::<f64>() - 0.5, rng.random::<f64>() - 0.5)
     }
 }
 
 #[cfg(test)]
 mod vec3_tests {
+    use rand::SeedableRng;
+
     use crate::assert_in_delta;
 
     use super::*;
@@ -643,7 +668,7 @@ mod vec3_tests {
 
     #[test]
     fn test_random() {
-        let v = Vec3::random();
+        let v = Vec3::random(&mut Rng::seed_from_u64(0));
         assert!(0.0 < v.x() && v.x() < 1.0);
         assert!(0.0 < v.y() && v.y() < 1.0);
         assert!(0.0 < v.z() && v.z() < 1.0);
@@ -651,7 +676,7 @@ mod vec3_tests {
 
     #[test]
     fn test_random_in_range() {
-        let v = Vec3::random_in_range(-10.0, 10.0);
+        let v = Vec3::random_in_range(&mut Rng::seed_from_u64(0), -10.0, 10.0);
         assert!(-10.0 < v.x() && v.x() < 10.0);
         assert!(-10.0 < v.y() && v.y() < 10.0);
         assert!(-10.0 < v.z() && v.z() < 10.0);
@@ -673,20 +698,30 @@ mod vec3_tests {
 
     #[test]
     fn test_random_in_unit_sphere() {
-        let v = Vec3::random_in_unit_sphere();
+        let v = Vec3::random_in_unit_sphere(&mut Rng::seed_from_u64(0));
         assert!(v.length() < 1.0);
     }
 
     #[test]
     fn test_random_unit_vector() {
-        let v = Vec3::random_unit();
+        let v = Vec3::random_unit(&mut Rng::seed_from_u64(0));
         assert_in_delta!(v.length(), 1.0);
     }
 
+    #[test]
+    fn test_random_in_unit_disk() {
+        let mut rng = Rng::seed_from_u64(0);
+        for _ in 0..100 {
+            let p = Vec3::random_in_unit_disk(&mut rng);
+            assert!(p.length() <= 1.0 + 1e-12);
+            assert_eq!(p.z(), 0.0);
+        }
+    }
+
     #[test]
     fn test_random_on_hemisphere() {
         let n = vec3!(0.0, 1.0, 0.0);
-        let v = Vec3::random_on_hemisphere(&n);
+        let v = Vec3::random_on_hemisphere(&mut Rng::seed_from_u64(0), &n);
 
         assert_in_delta!(v.length(), 1.0);
         assert!(v.y() > 0.0);
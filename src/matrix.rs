@@ -0,0 +1,218 @@
+use std::ops;
+
+use crate::{
+    math::{Point3, Vec3},
+    vec3,
+};
+
+/// A 4x4 matrix of an affine transform, stored row by row and applied to
+/// column vectors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4(pub [[f64; 4]; 4]);
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        Self([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translate(offset: &Vec3) -> Self {
+        Self([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scale(factors: &Vec3) -> Self {
+        Self([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// A counter-clockwise rotation by `angle` degrees about `axis`.
+    pub fn rotate(axis: &Vec3, angle: f64) -> Self {
+        let a = axis.unit();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let t = 1.0 - cos;
+        Self([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotate_x(angle: f64) -> Self {
+        Self::rotate(&vec3!(1.0, 0.0, 0.0), angle)
+    }
+
+    pub fn rotate_y(angle: f64) -> Self {
+        Self::rotate(&vec3!(0.0, 1.0, 0.0), angle)
+    }
+
+    pub fn rotate_z(angle: f64) -> Self {
+        Self::rotate(&vec3!(0.0, 0.0, 1.0), angle)
+    }
+
+    pub fn transpose(&self) -> Self {
+        let m = &self.0;
+        Self(std::array::from_fn(|i| std::array::from_fn(|j| m[j][i])))
+    }
+
+    /// The inverse by Gauss-Jordan elimination, or `None` if the matrix is
+    /// singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.0;
+        let mut inv = Self::identity().0;
+
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let p = a[col][col];
+            for k in 0..4 {
+                a[col][k] /= p;
+                inv[col][k] /= p;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let f = a[row][col];
+                    for k in 0..4 {
+                        a[row][k] -= f * a[col][k];
+                        inv[row][k] -= f * inv[col][k];
+                    }
+                }
+            }
+        }
+
+        Some(Self(inv))
+    }
+
+    /// The determinant of the upper 3x3 block, i.e. how much the transform
+    /// scales volumes.
+    pub fn determinant3(&self) -> f64 {
+        let m = &self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.0;
+        let row = |r: &[f64; 4]| r[0] * p.x() + r[1] * p.y() + r[2] * p.z() + r[3];
+        vec3!(row(&m[0]), row(&m[1]), row(&m[2]))
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.0;
+        let row = |r: &[f64; 4]| r[0] * v.x() + r[1] * v.y() + r[2] * v.z();
+        vec3!(row(&m[0]), row(&m[1]), row(&m[2]))
+    }
+}
+
+impl ops::Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        Mat4(std::array::from_fn(|i| {
+            std::array::from_fn(|j| (0..4).map(|k| self.0[i][k] * rhs.0[k][j]).sum())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{assert_in_delta, point};
+
+    use super::*;
+
+    fn assert_vec_eq(a: Vec3, b: Vec3) {
+        assert_in_delta!(a.x(), b.x(), 1e-9);
+        assert_in_delta!(a.y(), b.y(), 1e-9);
+        assert_in_delta!(a.z(), b.z(), 1e-9);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let p = point!(1.0, 0.0, 0.0);
+        assert_vec_eq(
+            Mat4::rotate_z(90.0).transform_point(&p),
+            point!(0.0, 1.0, 0.0),
+        );
+        assert_vec_eq(
+            Mat4::rotate_y(90.0).transform_point(&p),
+            point!(0.0, 0.0, -1.0),
+        );
+        assert_vec_eq(
+            Mat4::rotate_x(90.0).transform_point(&point!(0.0, 1.0, 0.0)),
+            point!(0.0, 0.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn test_compose() {
+        let m = Mat4::translate(&vec3!(1.0, 2.0, 3.0)) * Mat4::scale(&vec3!(2.0, 2.0, 2.0));
+        assert_vec_eq(
+            m.transform_point(&point!(1.0, 1.0, 1.0)),
+            point!(3.0, 4.0, 5.0),
+        );
+        assert_vec_eq(
+            m.transform_vector(&vec3!(1.0, 1.0, 1.0)),
+            vec3!(2.0, 2.0, 2.0),
+        );
+        assert_in_delta!(m.determinant3(), 8.0);
+    }
+
+    #[test]
+    fn test_inverse() {
+        let m = Mat4::translate(&vec3!(1.0, -2.0, 3.0))
+            * Mat4::rotate(&vec3!(1.0, 1.0, 0.0), 33.0)
+            * Mat4::scale(&vec3!(2.0, 0.5, 3.0));
+        let id = m * m.inverse().unwrap();
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert_in_delta!(id.0[i][j], expected, 1e-9);
+            }
+        }
+        assert!(Mat4::scale(&vec3!(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+}
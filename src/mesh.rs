@@ -0,0 +1,335 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    bvh::{BvhOptions, BVH},
+    group::Group,
+    interval::Interval,
+    material::Material,
+    math::{Point3, Vec2, Vec3},
+    object::{area_to_solid_angle, Hit, Object},
+    ray::Ray,
+    rng::Rng,
+    triangle::{intersect, sample_point, triangle_bbox},
+};
+
+/// One triangle of a mesh, as indices into the shared attribute arrays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Face {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+#[derive(Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub faces: Vec<Face>,
+}
+
+impl MeshData {
+    /// Builds mesh data where normals and UVs, when given, are indexed the
+    /// same way as positions.
+    pub fn indexed(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<Vec2>,
+        indices: &[[usize; 3]],
+    ) -> Self {
+        let faces = indices
+            .iter()
+            .map(|&idx| Face {
+                positions: idx,
+                normals: (!normals.is_empty()).then_some(idx),
+                uvs: (!uvs.is_empty()).then_some(idx),
+            })
+            .collect();
+        Self {
+            positions,
+            normals,
+            uvs,
+            faces,
+        }
+    }
+
+    /// Checks that every face index refers to an existing attribute.
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, face) in self.faces.iter().enumerate() {
+            let checks = [
+                ("position", Some(face.positions), self.positions.len()),
+                ("normal", face.normals, self.normals.len()),
+                ("uv", face.uvs, self.uvs.len()),
+            ];
+            for (attr, idx, len) in checks {
+                if let Some(bad) = idx.and_then(|idx| idx.into_iter().find(|&j| j >= len)) {
+                    return Err(format!(
+                        "face {} has {} index {} out of range",
+                        i, attr, bad
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+    e1: Vec3,
+    e2: Vec3,
+    normal: Vec3,
+    area: f64,
+}
+
+impl MeshTriangle {
+    fn new(mesh: &Arc<MeshData>, face: usize, mat: &Arc<dyn Material>) -> Self {
+        let [a, b, c] = mesh.faces[face].positions.map(|i| mesh.positions[i]);
+        let e1 = b - a;
+        let e2 = c - a;
+        let n = e1.cross(&e2);
+        Self {
+            mesh: Arc::clone(mesh),
+            face,
+            mat: Arc::clone(mat),
+            bbox: triangle_bbox(a, b, c),
+            e1,
+            e2,
+            normal: n.unit(),
+            area: 0.5 * n.length(),
+        }
+    }
+
+    fn a(&self) -> Point3 {
+        self.mesh.positions[self.mesh.faces[self.face].positions[0]]
+    }
+}
+
+impl Object for MeshTriangle {
+    fn hit(&self, r: &Ray, ray_t: &Interval, _rng: &mut Rng) -> Option<Hit> {
+        let face = &self.mesh.faces[self.face];
+        let (t, bary) = intersect(&self.a(), &self.e1, &self.e2, r, ray_t)?;
+        let weights = [1.0 - bary.u() - bary.v(), bary.u(), bary.v()];
+
+        let uv = match face.uvs {
+            Some(idx) => (0..3).fold(Vec2::default(), |acc, k| {
+                acc + weights[k] * self.mesh.uvs[idx[k]]
+            }),
+            None => bary,
+        };
+
+        let mut hit = Hit::new(t, r.at(t), r, self.normal, uv, &self.mat);
+
+        if let Some(idx) = face.normals {
+            let shading = (0..3)
+                .fold(Vec3::default(), |acc, k| {
+                    acc + weights[k] * self.mesh.normals[idx[k]]
+                })
+                .unit();
+            hit.normal = if hit.front_face { shading } else { -shading };
+        }
+
+        Some(hit)
+    }
+
+    fn bbox(&self) -> &AABB {
+        &self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64, rng: &mut Rng) -> f64 {
+        match self.hit(
+            &Ray::new(*origin, *direction, time),
+            &Interval::from(0.001),
+            rng,
+        ) {
+            Some(hit) => area_to_solid_angle(hit.t, direction, &self.normal, self.area),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, _time: f64, rng: &mut Rng) -> Vec3 {
+        sample_point(rng, &self.a(), &self.e1, &self.e2) - *origin
+    }
+
+    fn collect_lights(&self, lights: &mut Vec<Box<dyn Object>>) {
+        if self.mat.is_emissive() {
+            lights.push(Box::new(self.clone()));
+        }
+    }
+}
+
+/// A triangle mesh sharing its vertex attributes between faces, with its own
+/// BVH over the faces.
+pub struct Mesh {
+    pub data: Arc<MeshData>,
+    bvh: Box<dyn Object>,
+}
+
+impl Mesh {
+    pub fn new(data: MeshData, mat: &Arc<dyn Material>, options: &BvhOptions) -> Self {
+        let mats = vec![Arc::clone(mat); data.faces.len()];
+        Self::with_materials(data, &mats, options)
+    }
+
+    /// Builds a mesh where face `i` uses `mats[i]`, with its BVH built
+    /// according to `options`.
+    pub fn with_materials(
+        data: MeshData,
+        mats: &[Arc<dyn Material>],
+        options: &BvhOptions,
+    ) -> Self {
+        let data = Arc::new(data);
+        let triangles: Vec<Box<dyn Object>> = mats
+            .iter()
+            .enumerate()
+            .map(|(face, mat)| Box::new(MeshTriangle::new(&data, face, mat)) as Box<dyn Object>)
+            .collect();
+        let bvh = if triangles.is_empty() {
+            Box::new(Group::default())
+        } else {
+            BVH::build(triangles, options)
+        };
+        Self { data, bvh }
+    }
+}
+
+impl Object for Mesh {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> Option<Hit> {
+        self.bvh.hit(r, ray_t, rng)
+    }
+
+    fn bbox(&self) -> &AABB {
+        self.bvh.bbox()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64, rng: &mut Rng) -> f64 {
+        self.bvh.pdf_value(origin, direction, time, rng)
+    }
+
+    fn random(&self, origin: &Point3, time: f64, rng: &mut Rng) -> Vec3 {
+        self.bvh.random(origin, time, rng)
+    }
+
+    fn collect_lights(&self, lights: &mut Vec<Box<dyn Object>>) {
+        self.bvh.collect_lights(lights);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use crate::{assert_in_delta, color::Color, lambertian::Lambertian, point, rgb, vec3};
+
+    use super::*;
+
+    fn rng() -> Rng {
+        Rng::seed_from_u64(0)
+    }
+
+    // A 2x2 square in the z = 0 plane, split into two faces that share the
+    // diagonal from (0, 0) to (2, 2).
+    fn square(normals: Vec<Vec3>) -> MeshData {
+        MeshData::indexed(
+            vec![
+                point!(0.0, 0.0, 0.0),
+                point!(2.0, 0.0, 0.0),
+                point!(2.0, 2.0, 0.0),
+                point!(0.0, 2.0, 0.0),
+            ],
+            normals,
+            vec![
+                Vec2::new(0.0, 0.0),
+                Vec2::new(1.0, 0.0),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(0.0, 1.0),
+            ],
+            &[[0, 1, 2], [0, 2, 3]],
+        )
+    }
+
+    fn mesh(data: MeshData) -> Mesh {
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::solid(rgb!(0.5)));
+        Mesh::new(data, &mat, &BvhOptions::default())
+    }
+
+    #[test]
+    fn test_hit() {
+        let mesh = mesh(square(vec![]));
+        for (x, y) in [(1.5, 0.5), (0.5, 1.5)] {
+            let r = Ray::new(point!(x, y, 1.0), vec3!(0.0, 0.0, -1.0), 0.0);
+            let hit = mesh.hit(&r, &Interval::from(0.001), &mut rng()).unwrap();
+            assert_in_delta!(hit.t, 1.0);
+            assert_eq!(hit.p, point!(x, y, 0.0));
+            assert_eq!(hit.normal, vec3!(0.0, 0.0, 1.0));
+            // The UVs are interpolated from the shared vertices.
+            assert_in_delta!(hit.uv.u(), x / 2.0);
+            assert_in_delta!(hit.uv.v(), y / 2.0);
+        }
+
+        let outside = Ray::new(point!(2.5, 0.5, 1.0), vec3!(0.0, 0.0, -1.0), 0.0);
+        assert!(mesh
+            .hit(&outside, &Interval::from(0.001), &mut rng())
+            .is_none());
+    }
+
+    #[test]
+    fn test_hit_normals() {
+        let tilted = vec3!(1.0, 0.0, 1.0).unit();
+        let up = vec3!(0.0, 0.0, 1.0);
+        let mesh = mesh(square(vec![up, tilted, tilted, up]));
+
+        let r = Ray::new(point!(1.0, 0.5, 1.0), vec3!(0.0, 0.0, -1.0), 0.0);
+        let hit = mesh.hit(&r, &Interval::from(0.001), &mut rng()).unwrap();
+        assert_eq!(hit.normal, (0.5 * up + 0.5 * tilted).unit());
+
+        let back = Ray::new(point!(1.0, 0.5, -1.0), vec3!(0.0, 0.0, 1.0), 0.0);
+        let hit = mesh.hit(&back, &Interval::from(0.001), &mut rng()).unwrap();
+        assert_eq!(hit.normal, -(0.5 * up + 0.5 * tilted).unit());
+    }
+
+    #[test]
+    fn test_bbox() {
+        let square = mesh(square(vec![]));
+        let bbox = square.bbox();
+        // The box may be padded a little.
+        assert_in_delta!(bbox.x.min, 0.0, 1e-3);
+        assert_in_delta!(bbox.x.max, 2.0, 1e-3);
+        assert_in_delta!(bbox.y.min, 0.0, 1e-3);
+        assert_in_delta!(bbox.y.max, 2.0, 1e-3);
+        assert!(bbox.z.contains(0.0));
+
+        // A mesh without faces is empty.
+        let empty = mesh(MeshData::default());
+        assert!(empty
+            .hit(
+                &Ray::new(point!(0.0, 0.0, 1.0), vec3!(0.0, 0.0, -1.0), 0.0),
+                &Interval::from(0.001),
+                &mut rng()
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(square(vec![]).validate().is_ok());
+
+        let mut data = square(vec![]);
+        data.faces[1].positions[2] = 4;
+        assert_eq!(
+            data.validate(),
+            Err("face 1 has position index 4 out of range".into())
+        );
+
+        let data = square(vec![vec3!(0.0, 0.0, 1.0)]);
+        assert_eq!(
+            data.validate(),
+            Err("face 0 has normal index 1 out of range".into())
+        );
+    }
+}
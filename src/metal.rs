@@ -6,6 +6,7 @@ use crate::{
     math::Vec3,
     object::Hit,
     ray::Ray,
+    rng::Rng,
     solid_color::SolidColor,
     texture::Texture,
 };
@@ -25,17 +26,17 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<Scatter> {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, rng: &mut Rng) -> Option<Scatter> {
         let mut reflected = r_in.direction.reflect(&hit.normal);
-        reflected = reflected.unit() + (self.fuzz * Vec3::random_unit());
+        reflected = reflected.unit() + (self.fuzz * Vec3::random_unit(rng));
 
         if reflected.dot(&hit.normal) <= 0.0 {
             return None;
         }
 
-        Some(Scatter {
-            att: self.tex.sample_tex(&hit.uv, &hit.p),
-            ray: Ray::new(hit.p, reflected, r_in.time),
-        })
+        Some(Scatter::specular(
+            self.tex.sample_tex(&hit.uv, &hit.p),
+            Ray::new(hit.p, reflected, r_in.time),
+        ))
     }
 }
@@ -0,0 +1,401 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    color::Color,
+    dielectric::Dielectric,
+    diffuse_light::DiffuseLight,
+    error::Error,
+    image::Image,
+    lambertian::Lambertian,
+    material::Material,
+    math::{Vec2, Vec3},
+    mesh::{Face, MeshData},
+    metal::Metal,
+    rgb, vec3,
+};
+
+/// Geometry read from a Wavefront OBJ file, along with the MTL material used
+/// by each face.
+#[derive(Debug, Default)]
+pub struct ObjModel {
+    pub data: MeshData,
+    pub face_materials: Vec<Option<String>>,
+    pub material_libs: Vec<String>,
+}
+
+fn parse_floats<const N: usize>(args: &[&str], line: usize) -> Result<[f64; N], Error> {
+    if args.len() < N {
+        return Err(Error::Model(format!(
+            "line {}: expected {} values",
+            line, N
+        )));
+    }
+    let mut out = [0.0; N];
+    for (o, a) in out.iter_mut().zip(args) {
+        *o = a
+            .parse()
+            .map_err(|_| Error::Model(format!("line {}: invalid number {:?}", line, a)))?;
+    }
+    Ok(out)
+}
+
+fn parse_color(args: &[&str], line: usize) -> Result<Color, Error> {
+    let [r, g, b] = parse_floats(args, line)?;
+    Ok(rgb!(r, g, b))
+}
+
+// OBJ indices are 1-based, negative indices count back from the latest element.
+fn resolve_index(idx: &str, len: usize, line: usize) -> Result<usize, Error> {
+    let i: i64 = idx
+        .parse()
+        .map_err(|_| Error::Model(format!("line {}: invalid index {:?}", line, idx)))?;
+    let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(Error::Model(format!(
+            "line {}: index {} out of range",
+            line, idx
+        )));
+    }
+    Ok(resolved as usize)
+}
+
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+fn parse_face_vertex(vert: &str, model: &ObjModel, line: usize) -> Result<FaceVertex, Error> {
+    let mut parts = vert.split('/');
+    let v = resolve_index(
+        parts.next().unwrap_or_default(),
+        model.data.positions.len(),
+        line,
+    )?;
+    let vt = match parts.next() {
+        Some(idx) if !idx.is_empty() => Some(resolve_index(idx, model.data.uvs.len(), line)?),
+        _ => None,
+    };
+    let vn = match parts.next() {
+        Some(idx) if !idx.is_empty() => Some(resolve_index(idx, model.data.normals.len(), line)?),
+        _ => None,
+    };
+    Ok((v, vt, vn))
+}
+
+impl ObjModel {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut model = ObjModel::default();
+        let mut material: Option<String> = None;
+
+        for (n, text) in source.lines().enumerate() {
+            let line = n + 1;
+            let text = text.split('#').next().unwrap_or_default();
+            let mut tokens = text.split_whitespace();
+            let Some(keyword) = tokens.next() else {
+                continue;
+            };
+            let args: Vec<&str> = tokens.collect();
+
+            match keyword {
+                "v" => {
+                    let [x, y, z] = parse_floats(&args, line)?;
+                    model.data.positions.push(vec3!(x, y, z));
+                }
+                "vn" => {
+                    let [x, y, z] = parse_floats(&args, line)?;
+                    model.data.normals.push(vec3!(x, y, z).unit());
+                }
+                "vt" => {
+                    let [u, v] = parse_floats(&args, line)?;
+                    model.data.uvs.push(Vec2::new(u, v));
+                }
+                "f" => {
+                    let verts = args
+                        .iter()
+                        .map(|v| parse_face_vertex(v, &model, line))
+                        .collect::<Result<Vec<FaceVertex>, Error>>()?;
+                    if verts.len() < 3 {
+                        return Err(Error::Model(format!(
+                            "line {}: face needs at least 3 vertices",
+                            line
+                        )));
+                    }
+                    // Triangulate polygons as a fan around the first vertex.
+                    for k in 1..verts.len() - 1 {
+                        let tri = [verts[0], verts[k], verts[k + 1]];
+                        model.data.faces.push(Face {
+                            positions: tri.map(|v| v.0),
+                            uvs: tri
+                                .iter()
+                                .all(|v| v.1.is_some())
+                                .then(|| tri.map(|v| v.1.unwrap_or_default())),
+                            normals: tri
+                                .iter()
+                                .all(|v| v.2.is_some())
+                                .then(|| tri.map(|v| v.2.unwrap_or_default())),
+                        });
+                        model.face_materials.push(material.clone());
+                    }
+                }
+                "usemtl" => material = args.first().map(|m| m.to_string()),
+                "mtllib" => model
+                    .material_libs
+                    .extend(args.iter().map(|lib| lib.to_string())),
+                _ => {}
+            }
+        }
+
+        model.smooth_missing_normals();
+        Ok(model)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    // Faces without explicit normals get area-weighted vertex normals, averaged
+    // over every face sharing the vertex.
+    fn smooth_missing_normals(&mut self) {
+        let data = &mut self.data;
+        if data.faces.iter().all(|f| f.normals.is_some()) {
+            return;
+        }
+
+        let mut smooth = vec![Vec3::default(); data.positions.len()];
+        for face in &data.faces {
+            let [a, b, c] = face.positions.map(|i| data.positions[i]);
+            let n = (b - a).cross(&(c - a));
+            for i in face.positions {
+                smooth[i] += n;
+            }
+        }
+
+        let offset = data.normals.len();
+        data.normals.extend(smooth.into_iter().map(|n| {
+            if n.near_zero() {
+                vec3!(0.0, 1.0, 0.0)
+            } else {
+                n.unit()
+            }
+        }));
+
+        for face in data.faces.iter_mut().filter(|f| f.normals.is_none()) {
+            face.normals = Some(face.positions.map(|i| offset + i));
+        }
+    }
+}
+
+/// The subset of an MTL material definition the renderer understands.
+#[derive(Debug, Clone)]
+pub struct MtlMaterial {
+    pub kd: Color,
+    pub ks: Color,
+    pub ke: Color,
+    pub ns: f64,
+    pub ni: f64,
+    pub dissolve: f64,
+    pub illum: u32,
+    pub map_kd: Option<PathBuf>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            kd: rgb!(0.8),
+            ks: rgb!(0.0),
+            ke: rgb!(0.0),
+            ns: 0.0,
+            ni: 1.5,
+            dissolve: 1.0,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+}
+
+impl MtlMaterial {
+    /// Maps the material onto the closest built-in material: emissive materials
+    /// become `DiffuseLight`, transparent ones `Dielectric`, mirror-like ones
+    /// `Metal` and everything else `Lambertian`.
+    pub fn to_material(&self) -> Result<Arc<dyn Material>, Error> {
+        if !self.ke.near_zero() {
+            return Ok(Arc::new(DiffuseLight::solid(self.ke)));
+        }
+
+        if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            return Ok(Arc::new(Dielectric {
+                refraction_index: self.ni,
+            }));
+        }
+
+        if matches!(self.illum, 3 | 5 | 8) && !self.ks.near_zero() {
+            // Convert the Phong exponent to a roughness-like fuzz factor.
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt().clamp(0.0, 1.0);
+            return Ok(Arc::new(Metal::solid(self.ks, fuzz)));
+        }
+
+        match &self.map_kd {
+            Some(path) => {
+                let tex = Image::load(path.to_str().unwrap_or_default())?;
+                Ok(Arc::new(Lambertian { tex: Arc::new(tex) }))
+            }
+            None => Ok(Arc::new(Lambertian::solid(self.kd))),
+        }
+    }
+}
+
+pub fn parse_mtl(source: &str, dir: &Path) -> Result<HashMap<String, MtlMaterial>, Error> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (n, text) in source.lines().enumerate() {
+        let line = n + 1;
+        let text = text.split('#').next().unwrap_or_default();
+        let mut tokens = text.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if let Some((name, mtl)) = current.take() {
+                materials.insert(name, mtl);
+            }
+            let name = args.join(" ");
+            current = Some((name, MtlMaterial::default()));
+            continue;
+        }
+
+        let Some((_, mtl)) = current.as_mut() else {
+            continue;
+        };
+
+        match keyword {
+            "Kd" => mtl.kd = parse_color(&args, line)?,
+            "Ks" => mtl.ks = parse_color(&args, line)?,
+            "Ke" => mtl.ke = parse_color(&args, line)?,
+            "Ns" => mtl.ns = parse_floats::<1>(&args, line)?[0],
+            "Ni" => mtl.ni = parse_floats::<1>(&args, line)?[0],
+            "d" => mtl.dissolve = parse_floats::<1>(&args, line)?[0],
+            "Tr" => mtl.dissolve = 1.0 - parse_floats::<1>(&args, line)?[0],
+            "illum" => mtl.illum = parse_floats::<1>(&args, line)?[0] as u32,
+            // Texture options come before the file name, which is always last.
+            "map_Kd" => mtl.map_kd = args.last().map(|file| dir.join(file)),
+            _ => {}
+        }
+    }
+
+    if let Some((name, mtl)) = current {
+        materials.insert(name, mtl);
+    }
+
+    Ok(materials)
+}
+
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, MtlMaterial>, Error> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    parse_mtl(&fs::read_to_string(path)?, dir)
+}
+
+/// Loads every material from the model's MTL libraries, resolved relative to
+/// the directory of the OBJ file, adding the libraries and textures read to
+/// `files`.
+pub fn load_model_materials(
+    model: &ObjModel,
+    obj_path: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<HashMap<String, Arc<dyn Material>>, Error> {
+    let dir = obj_path.parent().unwrap_or(Path::new(""));
+    let mut materials = HashMap::new();
+    for lib in &model.material_libs {
+        let path = dir.join(lib);
+        for (name, mtl) in load_mtl(&path)? {
+            files.extend(mtl.map_kd.clone());
+            materials.insert(name, mtl.to_material()?);
+        }
+        files.push(path);
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assert_in_delta;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_triangle() {
+        let model = ObjModel::parse(
+            "# a triangle\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 2\nf 1/1/1 2/2/1 3/3/1\n",
+        )
+        .unwrap();
+        assert_eq!(model.data.positions.len(), 3);
+        assert_eq!(model.data.normals, vec![vec3!(0.0, 0.0, 1.0)]);
+        assert_eq!(
+            model.data.faces,
+            vec![Face {
+                positions: [0, 1, 2],
+                uvs: Some([0, 1, 2]),
+                normals: Some([0, 0, 0]),
+            }]
+        );
+        assert_eq!(model.face_materials, vec![None]);
+    }
+
+    #[test]
+    fn test_parse_quad_with_negative_indices() {
+        let model = ObjModel::parse(
+            "mtllib scene.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nusemtl red\nf -4 -3 -2 -1\n",
+        )
+        .unwrap();
+        assert_eq!(model.material_libs, vec!["scene.mtl".to_string()]);
+        assert_eq!(model.data.faces.len(), 2);
+        assert_eq!(model.data.faces[0].positions, [0, 1, 2]);
+        assert_eq!(model.data.faces[1].positions, [0, 2, 3]);
+        assert_eq!(
+            model.face_materials,
+            vec![Some("red".to_string()), Some("red".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_smooth_normals() {
+        // Two faces folded along the x axis share the vertices 1 and 2.
+        let model =
+            ObjModel::parse("v 0 0 0\nv 1 0 0\nv 0 0 1\nv 0 1 0\nf 1 3 2\nf 1 2 4\n").unwrap();
+        let normals = model.data.faces[0]
+            .normals
+            .unwrap()
+            .map(|i| model.data.normals[i]);
+        assert_eq!(normals[1], vec3!(0.0, 1.0, 0.0));
+        assert_eq!(normals[0], vec3!(0.0, 1.0, 1.0).unit());
+        assert_in_delta!(normals[2].length(), 1.0);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(ObjModel::parse("v 0 0\n").is_err());
+        assert!(ObjModel::parse("v 0 0 0\nf 1 2 3\n").is_err());
+        assert!(ObjModel::parse("v 0 0 0\nv 1 0 0\nf 1 2\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_mtl() {
+        let materials = parse_mtl(
+            "newmtl red\nKd 0.8 0.1 0.1\nnewmtl glass\nNi 1.33\nd 0.5\nnewmtl lamp\nKe 4 4 4\nnewmtl tex\nmap_Kd -bm 1 wood.png\n",
+            Path::new("models"),
+        )
+        .unwrap();
+        assert_eq!(materials["red"].kd, rgb!(0.8, 0.1, 0.1));
+        assert_eq!(materials["glass"].ni, 1.33);
+        assert_eq!(materials["glass"].dissolve, 0.5);
+        assert_eq!(materials["lamp"].ke, rgb!(4.0));
+        assert_eq!(
+            materials["tex"].map_kd,
+            Some(Path::new("models").join("wood.png"))
+        );
+    }
+}
@@ -6,8 +6,11 @@ use crate::{
     material::Material,
     math::{Point3, Vec2, Vec3},
     ray::Ray,
+    rng::Rng,
+    vec3,
 };
 
+/// The record of a ray-object intersection.
 pub struct Hit {
     pub t: f64,
     pub p: Point3,
@@ -42,7 +45,68 @@ impl Hit {
     }
 }
 
+/// Anything a ray can hit.
 pub trait Object: Send + Sync {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<Hit>;
+    fn hit(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> Option<Hit>;
     fn bbox(&self) -> &AABB;
+
+    /// The solid angle density of sampling `direction` from `origin` with
+    /// [`Object::random`].
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3, _time: f64, _rng: &mut Rng) -> f64 {
+        0.0
+    }
+
+    /// A random direction from `origin` towards the surface of the object.
+    fn random(&self, _origin: &Point3, _time: f64, _rng: &mut Rng) -> Vec3 {
+        vec3!(1.0, 0.0, 0.0)
+    }
+
+    /// Adds every emissive primitive that can be sampled directly to `lights`.
+    fn collect_lights(&self, _lights: &mut Vec<Box<dyn Object>>) {}
+}
+
+/// The density of sampling `direction` from `origin` by choosing one of
+/// `objects` uniformly and sampling it.
+pub fn uniform_pdf_value(
+    objects: &[Box<dyn Object>],
+    origin: &Point3,
+    direction: &Vec3,
+    time: f64,
+    rng: &mut Rng,
+) -> f64 {
+    if objects.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = objects
+        .iter()
+        .map(|object| object.pdf_value(origin, direction, time, rng))
+        .sum();
+    sum / objects.len() as f64
+}
+
+/// A random direction from `origin` towards one of `objects`, chosen
+/// uniformly.
+pub fn uniform_random(
+    objects: &[Box<dyn Object>],
+    origin: &Point3,
+    time: f64,
+    rng: &mut Rng,
+) -> Vec3 {
+    let n = objects.len();
+    if n == 0 {
+        return vec3!(1.0, 0.0, 0.0);
+    }
+    let index = ((rng.get_1d() * n as f64) as usize).min(n - 1);
+    objects[index].random(origin, time, rng)
+}
+
+// Converts an area density into a solid angle density for a point seen at
+// distance `t` along `direction` with the given surface normal.
+pub fn area_to_solid_angle(t: f64, direction: &Vec3, normal: &Vec3, area: f64) -> f64 {
+    let distance_squared = t * t * direction.length_squared();
+    let cosine = (direction.dot(normal) / direction.length()).abs();
+    if cosine <= 0.0 {
+        return 0.0;
+    }
+    distance_squared / (cosine * area)
 }
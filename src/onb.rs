@@ -0,0 +1,26 @@
+use crate::{math::Vec3, vec3};
+
+/// An orthonormal basis built around a single direction.
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(n: &Vec3) -> Self {
+        let w = n.unit();
+        let a = if w.x().abs() > 0.9 {
+            vec3!(0.0, 1.0, 0.0)
+        } else {
+            vec3!(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit();
+        let u = w.cross(&v);
+        Self { u, v, w }
+    }
+
+    pub fn transform(&self, v: &Vec3) -> Vec3 {
+        v.x() * self.u + v.y() * self.v + v.z() * self.w
+    }
+}
@@ -0,0 +1,208 @@
+use std::{fs::File, io::BufWriter, path::Path, str::FromStr};
+
+use image::{
+    codecs::{
+        jpeg::JpegEncoder,
+        pnm::{PnmEncoder, PnmSubtype, SampleEncoding},
+    },
+    ExtendedColorType, ImageEncoder, ImageFormat, Rgb, Rgb32FImage, RgbImage,
+};
+
+use crate::{color::Color, error::Error, film::Film, rgb};
+
+const JPEG_QUALITY: u8 = 95;
+
+/// A rendered frame of linear radiance values, stored row by row.
+#[derive(Clone)]
+pub struct FrameBuffer {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Color>,
+}
+
+impl FrameBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![rgb!(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> Color {
+        self.pixels[j * self.width + i]
+    }
+
+    pub fn set(&mut self, i: usize, j: usize, color: Color) {
+        self.pixels[j * self.width + i] = color;
+    }
+
+    pub fn row_mut(&mut self, j: usize) -> &mut [Color] {
+        let start = j * self.width;
+        &mut self.pixels[start..start + self.width]
+    }
+
+    /// Returns a copy of the frame with `f` applied to every pixel.
+    pub fn map(&self, f: impl Fn(&Color) -> Color) -> FrameBuffer {
+        FrameBuffer {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(f).collect(),
+        }
+    }
+
+    /// Develops the frame with `film` into 8-bit sRGB.
+    pub fn to_rgb8(&self, film: &Film) -> RgbImage {
+        RgbImage::from_fn(self.width as u32, self.height as u32, |i, j| {
+            let (r, g, b) = film.to_pixel(&self.get(i as usize, j as usize));
+            Rgb([r, g, b])
+        })
+    }
+
+    pub fn to_rgb32f(&self) -> Rgb32FImage {
+        Rgb32FImage::from_fn(self.width as u32, self.height as u32, |i, j| {
+            let c = self.get(i as usize, j as usize);
+            Rgb([c.r() as f32, c.g() as f32, c.b() as f32])
+        })
+    }
+}
+
+/// An image file format the renderer can write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Tga,
+    Hdr,
+    Exr,
+    Ppm,
+    PpmAscii,
+}
+
+impl OutputFormat {
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        ext.parse()
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "jpg" | "jpeg" => Ok(OutputFormat::Jpeg),
+            "tga" => Ok(OutputFormat::Tga),
+            "hdr" => Ok(OutputFormat::Hdr),
+            "exr" => Ok(OutputFormat::Exr),
+            "ppm" | "p6" => Ok(OutputFormat::Ppm),
+            "ppm-ascii" | "p3" => Ok(OutputFormat::PpmAscii),
+            _ => Err(Error::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+fn write_ppm(
+    buffer: &FrameBuffer,
+    film: &Film,
+    path: &Path,
+    encoding: SampleEncoding,
+) -> Result<(), Error> {
+    let writer = BufWriter::new(File::create(path)?);
+    let encoder = PnmEncoder::new(writer).with_subtype(PnmSubtype::Pixmap(encoding));
+    encoder.write_image(
+        buffer.to_rgb8(film).as_raw(),
+        buffer.width as u32,
+        buffer.height as u32,
+        ExtendedColorType::Rgb8,
+    )?;
+    Ok(())
+}
+
+fn write_jpeg(buffer: &FrameBuffer, film: &Film, path: &Path) -> Result<(), Error> {
+    let writer = BufWriter::new(File::create(path)?);
+    let encoder = JpegEncoder::new_with_quality(writer, JPEG_QUALITY);
+    encoder.write_image(
+        buffer.to_rgb8(film).as_raw(),
+        buffer.width as u32,
+        buffer.height as u32,
+        ExtendedColorType::Rgb8,
+    )?;
+    Ok(())
+}
+
+/// Writes `buffer` to `path`. HDR and EXR keep the linear radiance, only
+/// scaled by the film exposure, every other format is developed with `film`
+/// and quantized to 8 bits.
+pub fn write_image(
+    buffer: &FrameBuffer,
+    film: &Film,
+    path: &Path,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    match format {
+        OutputFormat::Png => buffer
+            .to_rgb8(film)
+            .save_with_format(path, ImageFormat::Png)?,
+        OutputFormat::Jpeg => write_jpeg(buffer, film, path)?,
+        OutputFormat::Tga => buffer
+            .to_rgb8(film)
+            .save_with_format(path, ImageFormat::Tga)?,
+        OutputFormat::Hdr => buffer
+            .map(|c| film.expose(c))
+            .to_rgb32f()
+            .save_with_format(path, ImageFormat::Hdr)?,
+        OutputFormat::Exr => buffer
+            .map(|c| film.expose(c))
+            .to_rgb32f()
+            .save_with_format(path, ImageFormat::OpenExr)?,
+        OutputFormat::Ppm => write_ppm(buffer, film, path, SampleEncoding::Binary)?,
+        OutputFormat::PpmAscii => write_ppm(buffer, film, path, SampleEncoding::Ascii)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out.PNG")).unwrap(),
+            OutputFormat::Png
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out.jpeg")).unwrap(),
+            OutputFormat::Jpeg
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("renders/out.exr")).unwrap(),
+            OutputFormat::Exr
+        );
+        assert!(OutputFormat::from_path(Path::new("out")).is_err());
+        assert!(OutputFormat::from_path(Path::new("out.gif")).is_err());
+    }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!("ppm".parse::<OutputFormat>().unwrap(), OutputFormat::Ppm);
+        assert_eq!(
+            "ppm-ascii".parse::<OutputFormat>().unwrap(),
+            OutputFormat::PpmAscii
+        );
+    }
+
+    #[test]
+    fn test_to_rgb8() {
+        let mut buffer = FrameBuffer::new(2, 1);
+        buffer.set(1, 0, rgb!(0.1, 0.2, 0.3));
+        let img = buffer.to_rgb8(&Film::default());
+        assert_eq!(img.get_pixel(0, 0), &Rgb([0, 0, 0]));
+        assert_eq!(img.get_pixel(1, 0), &Rgb([89, 124, 149]));
+    }
+}
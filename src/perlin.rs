@@ -1,19 +1,22 @@
 use exmex::Val;
 use smallvec::smallvec;
-use std::{array, collections::HashMap, sync::LazyLock};
+use std::{array, collections::HashMap, sync::Arc};
 
-use rand::{rng, seq::SliceRandom};
+use rand::{seq::SliceRandom, SeedableRng};
 
 use crate::{
     color::Color,
     error,
     expression::Expression,
     math::{Point3, Vec2, Vec3},
+    rng::Rng,
     texture::Texture,
     vec3,
 };
 
-pub static PERLIN: LazyLock<Perlin> = LazyLock::new(|| Perlin::new());
+// Mixed into the scene seed, so that with the default seed the noise
+// textures look the same as they always have.
+const PERLIN_SEED: u64 = 0x5eed;
 
 const POINT_COUNT: usize = 256;
 
@@ -46,22 +49,34 @@ pub struct Perlin {
     perm_z: [usize; POINT_COUNT],
 }
 
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::from_seed(0)
+    }
+}
+
 impl Perlin {
-    pub fn new() -> Self {
+    pub fn new(rng: &mut Rng) -> Self {
         let mut perm_x: [usize; POINT_COUNT] = array::from_fn(|i| i);
-        perm_x.shuffle(&mut rng());
+        perm_x.shuffle(rng);
         let mut perm_y: [usize; POINT_COUNT] = array::from_fn(|j| j);
-        perm_y.shuffle(&mut rng());
+        perm_y.shuffle(rng);
         let mut perm_z: [usize; POINT_COUNT] = array::from_fn(|k| k);
-        perm_z.shuffle(&mut rng());
+        perm_z.shuffle(rng);
         Self {
-            randvec: array::from_fn(|_| Vec3::random_in_range(-1.0, 1.0)),
+            randvec: array::from_fn(|_| Vec3::random_in_range(rng, -1.0, 1.0)),
             perm_x,
             perm_y,
             perm_z,
         }
     }
 
+    /// The noise tables of a scene rendered with `seed`, the same from one
+    /// render to the next.
+    pub fn from_seed(seed: u64) -> Self {
+        Self::new(&mut Rng::seed_from_u64(seed ^ PERLIN_SEED))
+    }
+
     pub fn noise(&self, p: &Point3) -> f64 {
         let fp = p.floored();
         let vec = *p - fp;
@@ -102,8 +117,8 @@ impl Perlin {
 pub struct Noise(Expression);
 
 impl Noise {
-    pub fn parse(expr: &str) -> Result<Self, error::Error> {
-        let expr = Expression::parse(expr)?;
+    pub fn parse(expr: &str, perlin: &Arc<Perlin>) -> Result<Self, error::Error> {
+        let expr = Expression::parse(expr, perlin)?;
         Ok(Self(expr))
     }
 }
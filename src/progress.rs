@@ -0,0 +1,142 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Receives progress events from the renderer. Work is counted in samples,
+/// so a frame has `width * height * samples` units in total.
+pub trait ProgressReporter: Sync {
+    /// Called once before rendering `total` units on `threads` threads, of
+    /// which `restored` were finished by an earlier run, such as a resumed
+    /// checkpoint.
+    fn start(&self, _total: u64, _restored: u64, _threads: usize) {}
+
+    /// Called from the render threads whenever more work is finished, with
+    /// `done` units finished so far.
+    fn progress(&self, done: u64, total: u64);
+
+    /// Called once after the last unit is finished.
+    fn finish(&self) {}
+}
+
+/// Embedders can pass a closure taking `(done, total)`.
+impl<F: Fn(u64, u64) + Sync> ProgressReporter for F {
+    fn progress(&self, done: u64, total: u64) {
+        self(done, total)
+    }
+}
+
+/// Reports nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuietProgress;
+
+impl ProgressReporter for QuietProgress {
+    fn progress(&self, _done: u64, _total: u64) {}
+}
+
+/// Prints the percentage done and the estimated time left on stderr.
+#[derive(Debug, Default)]
+pub struct TerminalProgress {
+    state: Mutex<Option<TerminalState>>,
+}
+
+#[derive(Debug)]
+struct TerminalState {
+    start: Instant,
+    // Units finished before `start`, left out of the rate.
+    restored: u64,
+    last_print: Option<(Instant, u64)>,
+}
+
+// How often the line is redrawn when the percentage doesn't change.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(500);
+
+// Estimates the time left from the rate of the units finished in `elapsed`,
+// which are those past the `restored` ones.
+fn time_left(elapsed: Duration, restored: u64, done: u64, total: u64) -> Option<Duration> {
+    let rendered = done.saturating_sub(restored);
+    (rendered > 0 && done < total).then(|| elapsed.mul_f64((total - done) as f64 / rendered as f64))
+}
+
+/// Formats `d` like `1h02m03s`, `2m03s` or `3s`.
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs_f64().round() as u64;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{}h{:02}m{:02}s", h, m, s)
+    } else if m > 0 {
+        format!("{}m{:02}s", m, s)
+    } else {
+        format!("{}s", s)
+    }
+}
+
+impl ProgressReporter for TerminalProgress {
+    fn start(&self, _total: u64, restored: u64, threads: usize) {
+        if threads > 1 {
+            eprintln!("RUNNING ON {} CPUS", threads);
+        }
+        *self.state.lock().unwrap() = Some(TerminalState {
+            start: Instant::now(),
+            restored,
+            last_print: None,
+        });
+    }
+
+    fn progress(&self, done: u64, total: u64) {
+        let mut state = self.state.lock().unwrap();
+        let state = state.get_or_insert_with(|| TerminalState {
+            start: Instant::now(),
+            restored: 0,
+            last_print: None,
+        });
+
+        let now = Instant::now();
+        let percent = done * 100 / total.max(1);
+        if let Some((at, last_percent)) = state.last_print {
+            if percent == last_percent && now - at < REDRAW_INTERVAL {
+                return;
+            }
+        }
+        state.last_print = Some((now, percent));
+
+        if let Some(left) = time_left(now - state.start, state.restored, done, total) {
+            eprint!(
+                "\rProgress: {}% (ETA {})        ",
+                percent,
+                format_duration(left)
+            );
+        } else {
+            eprint!("\rProgress: {}%                 ", percent);
+        }
+    }
+
+    fn finish(&self) {
+        *self.state.lock().unwrap() = None;
+        eprintln!("\rDone.                            ");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(400)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(123)), "2m03s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+    }
+
+    #[test]
+    fn test_time_left() {
+        let minute = Duration::from_secs(60);
+        assert_eq!(time_left(minute, 0, 0, 100), None);
+        assert_eq!(time_left(minute, 0, 25, 100), Some(minute * 3));
+        assert_eq!(time_left(minute, 0, 100, 100), None);
+        // Only the units rendered in this run set the rate.
+        assert_eq!(time_left(minute, 50, 50, 100), None);
+        assert_eq!(time_left(minute, 50, 75, 100), Some(minute));
+    }
+}
@@ -0,0 +1,578 @@
+use std::{
+    sync::atomic::AtomicU64,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    color::Color,
+    filter::Filter,
+    output::FrameBuffer,
+    progress::ProgressReporter,
+    rgb,
+    scene::{FilmSample, Scene},
+    stats::Counters,
+    thread_pool::{render_pass, PassProgress, RenderOptions},
+};
+
+/// Settings for rendering in passes over the whole image, so the running
+/// average can be looked at before the render is done.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressiveOptions {
+    /// Samples per pixel added by every pass.
+    pub pass_samples: u32,
+    /// Stops after this many samples per pixel instead of the camera's.
+    pub target_samples: Option<u32>,
+    /// Stops after the first pass that ends past this much time.
+    pub time_limit: Option<Duration>,
+    /// Takes a snapshot after every this many passes.
+    pub snapshot_passes: Option<u32>,
+    /// Takes a snapshot after a pass once this much time has passed since the
+    /// last one.
+    pub snapshot_interval: Option<Duration>,
+    /// Spends the samples where they are needed instead of evenly.
+    pub adaptive: Option<AdaptiveOptions>,
+}
+
+impl Default for ProgressiveOptions {
+    fn default() -> Self {
+        Self {
+            pass_samples: 1,
+            target_samples: None,
+            time_limit: None,
+            snapshot_passes: None,
+            snapshot_interval: None,
+            adaptive: None,
+        }
+    }
+}
+
+/// Settings for adaptive sampling. The target samples per pixel become a
+/// budget for the whole image: every pixel gets `min_samples`, then each pass
+/// gives more to the pixels with the largest error until all of them are
+/// below `threshold` or the budget is spent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveOptions {
+    /// Samples every pixel gets before its error is estimated, even when that
+    /// goes over the budget.
+    pub min_samples: u32,
+    /// The most samples a single pixel gets, by default four times the
+    /// target.
+    pub max_samples: Option<u32>,
+    /// A pixel is converged once the standard error of its mean luminance is
+    /// below this fraction of the mean.
+    pub threshold: f64,
+}
+
+impl Default for AdaptiveOptions {
+    fn default() -> Self {
+        Self {
+            min_samples: 16,
+            max_samples: None,
+            threshold: 0.01,
+        }
+    }
+}
+
+// Keeps the relative error of nearly black pixels from blowing up.
+const ERROR_FLOOR: f64 = 0.01;
+
+// The Mitchell and Lanczos filters have negative lobes, so a pixel with few
+// samples can have a total weight near zero or below. Dividing by it would
+// blow up or flip its color, so such pixels stay black.
+const MIN_WEIGHT: f64 = 1e-3;
+
+/// Running sums of filtered radiance, with the number of samples taken in
+/// every pixel and statistics of their luminance.
+#[derive(Clone)]
+pub struct Accumulation {
+    /// Summed radiance of the samples reaching every pixel, weighted by the
+    /// filter.
+    pub sums: FrameBuffer,
+    /// Summed filter weights of the samples reaching every pixel.
+    pub weights: Vec<f64>,
+    /// Summed luminance of the samples taken in every pixel.
+    pub luminance: Vec<f64>,
+    /// Summed squared luminance of the samples taken in every pixel.
+    pub luminance_sq: Vec<f64>,
+    pub samples: Vec<u32>,
+}
+
+impl Accumulation {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            sums: FrameBuffer::new(width, height),
+            weights: vec![0.0; width * height],
+            luminance: vec![0.0; width * height],
+            luminance_sq: vec![0.0; width * height],
+            samples: vec![0; width * height],
+        }
+    }
+
+    pub fn samples_at(&self, i: usize, j: usize) -> u32 {
+        self.samples[j * self.sums.width + i]
+    }
+
+    /// Adds a sample taken in pixel `(i, j)`, splatting it into every pixel
+    /// whose center `filter` reaches.
+    pub fn add_sample(&mut self, filter: &Filter, i: usize, j: usize, sample: &FilmSample) {
+        let width = self.sums.width;
+        let p = j * width + i;
+        let luminance = sample.color.luminance();
+        self.luminance[p] += luminance;
+        self.luminance_sq[p] += luminance * luminance;
+        self.samples[p] += 1;
+
+        let margin = filter.margin();
+        let (x0, y0) = (i.saturating_sub(margin), j.saturating_sub(margin));
+        let x1 = (i + margin + 1).min(width);
+        let y1 = (j + margin + 1).min(self.sums.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let w = filter.weight(sample.x - (x as f64 + 0.5), sample.y - (y as f64 + 0.5));
+                if w != 0.0 {
+                    self.sums.set(x, y, self.sums.get(x, y) + sample.color * w);
+                    self.weights[y * width + x] += w;
+                }
+            }
+        }
+    }
+
+    /// Adds everything in `other`, whose top left pixel is `(x, y)` here.
+    pub fn merge(&mut self, other: &Accumulation, x: usize, y: usize) {
+        for j in 0..other.sums.height {
+            for i in 0..other.sums.width {
+                let (p, q) = ((y + j) * self.sums.width + x + i, j * other.sums.width + i);
+                self.sums.set(
+                    x + i,
+                    y + j,
+                    self.sums.get(x + i, y + j) + other.sums.get(i, j),
+                );
+                self.weights[p] += other.weights[q];
+                self.luminance[p] += other.luminance[q];
+                self.luminance_sq[p] += other.luminance_sq[q];
+                self.samples[p] += other.samples[q];
+            }
+        }
+    }
+
+    /// The fewest samples behind any pixel.
+    pub fn min_samples(&self) -> u32 {
+        self.samples.iter().copied().min().unwrap_or(0)
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.samples.iter().map(|&n| n as u64).sum()
+    }
+
+    /// The estimated standard error of the mean luminance of pixel `(i, j)`,
+    /// relative to that mean. Infinite with fewer than two samples.
+    pub fn relative_error(&self, i: usize, j: usize) -> f64 {
+        let n = self.samples_at(i, j);
+        if n < 2 {
+            return f64::INFINITY;
+        }
+        let (p, n) = (j * self.sums.width + i, n as f64);
+        let mean = self.luminance[p] / n;
+        let mean_sq = self.luminance_sq[p] / n;
+        let variance = ((mean_sq - mean * mean) * n / (n - 1.0)).max(0.0);
+        (variance / n).sqrt() / (mean.abs() + ERROR_FLOOR)
+    }
+
+    /// The filtered radiance of every pixel, black where the samples that
+    /// reach it don't add up to a clearly positive weight yet.
+    pub fn average(&self) -> FrameBuffer {
+        let mut image = FrameBuffer::new(self.sums.width, self.sums.height);
+        for j in 0..image.height {
+            for i in 0..image.width {
+                let w = self.weights[j * image.width + i];
+                if w > MIN_WEIGHT {
+                    image.set(i, j, self.sums.get(i, j) * (1.0 / w));
+                }
+            }
+        }
+        image
+    }
+
+    /// Shows the samples behind every pixel, from black for none through
+    /// blue, green and yellow to white for the most.
+    pub fn sample_heatmap(&self) -> FrameBuffer {
+        let ramp = [
+            rgb!(0.0, 0.0, 0.0),
+            rgb!(0.0, 0.0, 1.0),
+            rgb!(0.0, 1.0, 0.0),
+            rgb!(1.0, 1.0, 0.0),
+            rgb!(1.0, 1.0, 1.0),
+        ];
+        let most = self.samples.iter().copied().max().unwrap_or(0).max(1) as f64;
+        let mut image = FrameBuffer::new(self.sums.width, self.sums.height);
+        for j in 0..image.height {
+            for i in 0..image.width {
+                let t = self.samples_at(i, j) as f64 / most * (ramp.len() - 1) as f64;
+                let k = (t as usize).min(ramp.len() - 2);
+                let f = t - k as f64;
+                image.set(i, j, ramp[k] * (1.0 - f) + ramp[k + 1] * f);
+            }
+        }
+        image
+    }
+}
+
+/// The result of a progressive render.
+pub struct ProgressiveRender {
+    pub acc: Accumulation,
+    pub counters: Counters,
+    /// Whether the time limit stopped the render.
+    pub out_of_time: bool,
+}
+
+impl ProgressiveRender {
+    /// Samples per pixel done before stopping.
+    pub fn samples(&self) -> u32 {
+        self.acc.min_samples()
+    }
+
+    /// The average samples per pixel.
+    pub fn mean_samples(&self) -> f64 {
+        self.acc.total_samples() as f64 / self.acc.samples.len().max(1) as f64
+    }
+}
+
+// Gives every pixel `count` more samples, up to `target`.
+fn uniform_plan(acc: &Accumulation, count: u32, target: u32) -> Vec<u32> {
+    acc.samples
+        .iter()
+        .map(|&n| count.min(target.saturating_sub(n)))
+        .collect()
+}
+
+// Brings every pixel up to the minimum first. After that, gives `count` more
+// samples to the unconverged pixels, largest error first, while the budget
+// lasts.
+fn adaptive_plan(
+    acc: &Accumulation,
+    adaptive: &AdaptiveOptions,
+    count: u32,
+    target: u32,
+) -> Vec<u32> {
+    if acc.min_samples() < adaptive.min_samples {
+        return uniform_plan(acc, adaptive.min_samples, adaptive.min_samples);
+    }
+
+    let budget = acc.samples.len() as u64 * target as u64;
+    let mut left = budget.saturating_sub(acc.total_samples());
+    let max = adaptive
+        .max_samples
+        .unwrap_or(target.saturating_mul(4))
+        .max(adaptive.min_samples);
+
+    let width = acc.sums.width;
+    let mut pixels: Vec<(usize, f64)> = (0..acc.samples.len())
+        .filter(|&p| acc.samples[p] < max)
+        .map(|p| (p, acc.relative_error(p % width, p / width)))
+        .filter(|&(_, error)| error >= adaptive.threshold)
+        .collect();
+    pixels.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut plan = vec![0; acc.samples.len()];
+    for (p, _) in pixels {
+        if left == 0 {
+            break;
+        }
+        let n = count.min(max - acc.samples[p]).min(left as u32);
+        plan[p] = n;
+        left -= n as u64;
+    }
+    plan
+}
+
+/// Renders `scene` in passes, adding samples to `acc`, which is empty or
+/// holds an earlier part of the same render. Calls `snapshot` with the
+/// accumulated samples when the options ask for one; the finished render is
+/// only returned, not passed to `snapshot`.
+///
+/// Passes use the same samples a single pass would, so rendering to the
+/// camera's sample count gives the same image as [`render`](crate::render).
+pub fn render_progressive<E>(
+    scene: &Scene,
+    options: &RenderOptions,
+    progressive: &ProgressiveOptions,
+    mut acc: Accumulation,
+    progress: &dyn ProgressReporter,
+    mut snapshot: impl FnMut(&Accumulation) -> Result<(), E>,
+) -> Result<ProgressiveRender, E> {
+    let camera = &scene.camera;
+    let target = progressive.target_samples.unwrap_or(camera.samples);
+    let count = progressive.pass_samples.max(1);
+    let next_plan = |acc: &Accumulation| match &progressive.adaptive {
+        Some(adaptive) => adaptive_plan(acc, adaptive, count, target),
+        None => uniform_plan(acc, count, target),
+    };
+
+    let pass_progress = PassProgress {
+        done: AtomicU64::new(acc.total_samples()),
+        total: (camera.image_width * camera.image_height) as u64 * target as u64,
+        reporter: progress,
+    };
+
+    let start = Instant::now();
+    let mut last_snapshot = start;
+    let mut counters = Counters::default();
+    let mut passes = 0;
+    let mut out_of_time = false;
+
+    progress.start(
+        pass_progress.total,
+        acc.total_samples(),
+        options.threads.max(1),
+    );
+    let mut plan = next_plan(&acc);
+    while plan.iter().any(|&n| n > 0) {
+        counters += render_pass(scene, options, &mut acc, &plan, &pass_progress);
+        passes += 1;
+
+        out_of_time = progressive.time_limit.is_some_and(|t| start.elapsed() >= t);
+        if out_of_time {
+            break;
+        }
+        plan = next_plan(&acc);
+        if plan.iter().all(|&n| n == 0) {
+            break;
+        }
+
+        let due = progressive
+            .snapshot_passes
+            .is_some_and(|n| n > 0 && passes % n == 0)
+            || progressive
+                .snapshot_interval
+                .is_some_and(|t| last_snapshot.elapsed() >= t);
+        if due {
+            snapshot(&acc)?;
+            last_snapshot = Instant::now();
+        }
+    }
+    progress.finish();
+
+    Ok(ProgressiveRender {
+        acc,
+        counters,
+        out_of_time,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+
+    use crate::{
+        color::Color, filter::FilterKind, progress::QuietProgress, rgb, test_data,
+        thread_pool::render,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_matches_single_pass() {
+        let scene = test_data::scene(8, 6, 10);
+        let options = RenderOptions {
+            threads: 2,
+            ..Default::default()
+        };
+        let progressive = ProgressiveOptions {
+            pass_samples: 3,
+            snapshot_passes: Some(2),
+            ..Default::default()
+        };
+
+        let mut snapshots = vec![];
+        let result = render_progressive(
+            &scene,
+            &options,
+            &progressive,
+            Accumulation::new(8, 6),
+            &QuietProgress,
+            |acc| {
+                snapshots.push(acc.min_samples());
+                Ok::<_, Infallible>(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(result.samples(), 10);
+        assert_eq!(snapshots, vec![6]);
+        let expected = render(&scene, &options).to_rgb32f();
+        for (a, b) in result
+            .acc
+            .average()
+            .to_rgb32f()
+            .pixels()
+            .zip(expected.pixels())
+        {
+            for c in 0..3 {
+                assert!((a[c] - b[c]).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stops_early() {
+        let scene = test_data::scene(8, 6, 10);
+        let options = RenderOptions::default();
+        let progressive = ProgressiveOptions {
+            target_samples: Some(4),
+            ..Default::default()
+        };
+        let result = render_progressive(
+            &scene,
+            &options,
+            &progressive,
+            Accumulation::new(8, 6),
+            &QuietProgress,
+            |_| Ok::<_, Infallible>(()),
+        )
+        .unwrap();
+        assert_eq!(result.samples(), 4);
+
+        // Resuming from there adds a single pass before running out of time.
+        let progressive = ProgressiveOptions {
+            time_limit: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let result = render_progressive(
+            &scene,
+            &options,
+            &progressive,
+            result.acc,
+            &QuietProgress,
+            |_| Err("no snapshots expected"),
+        )
+        .unwrap();
+        assert_eq!(result.samples(), 5);
+    }
+
+    #[test]
+    fn test_adaptive() {
+        let scene = test_data::scene(8, 6, 10);
+        let progressive = ProgressiveOptions {
+            pass_samples: 4,
+            adaptive: Some(AdaptiveOptions {
+                min_samples: 4,
+                max_samples: Some(24),
+                threshold: 0.05,
+            }),
+            ..Default::default()
+        };
+        let result = render_progressive(
+            &scene,
+            &RenderOptions::default(),
+            &progressive,
+            Accumulation::new(8, 6),
+            &QuietProgress,
+            |_| Ok::<_, Infallible>(()),
+        )
+        .unwrap();
+
+        let acc = &result.acc;
+        assert!(acc.total_samples() <= 8 * 6 * 10);
+        assert_eq!(result.samples(), 4);
+        assert!(acc.samples.iter().all(|&n| n <= 24));
+        // The corner only sees the smooth background, so it converges at
+        // once, while the noisiest pixels get as many samples as allowed.
+        assert!(acc.relative_error(0, 5) < 0.05);
+        assert_eq!(acc.samples_at(0, 5), 4);
+        assert!(acc.samples.contains(&24));
+    }
+
+    // A sample of `color` in the middle of pixel `(i, j)`.
+    fn sample(i: usize, j: usize, color: Color) -> FilmSample {
+        FilmSample {
+            x: i as f64 + 0.5,
+            y: j as f64 + 0.5,
+            color,
+        }
+    }
+
+    #[test]
+    fn test_relative_error() {
+        let filter = Filter::default();
+        let mut acc = Accumulation::new(2, 1);
+        acc.add_sample(&filter, 0, 0, &sample(0, 0, rgb!(1.0)));
+        assert_eq!(acc.relative_error(0, 0), f64::INFINITY);
+
+        // Samples of 0 and 2 have a variance of 2, so the error of their
+        // mean of 1 is 1.
+        acc.add_sample(&filter, 1, 0, &sample(1, 0, rgb!(0.0)));
+        acc.add_sample(&filter, 1, 0, &sample(1, 0, rgb!(2.0)));
+        assert!((acc.relative_error(1, 0) - 1.0 / (1.0 + ERROR_FLOOR)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_splat() {
+        let filter = Filter::new(FilterKind::Tent);
+        let mut acc = Accumulation::new(3, 2);
+        acc.add_sample(&filter, 1, 0, &sample(1, 0, rgb!(1.0)));
+        acc.add_sample(&filter, 2, 1, &sample(2, 1, rgb!(0.0)));
+
+        // Samples reach only their own pixel center with a radius of 1, but
+        // the sample between two pixels is shared by them.
+        assert_eq!(acc.weights, vec![0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        acc.add_sample(
+            &filter,
+            0,
+            1,
+            &FilmSample {
+                x: 1.0,
+                y: 1.5,
+                color: rgb!(1.0),
+            },
+        );
+        assert_eq!(acc.samples, vec![0, 1, 0, 1, 0, 1]);
+        assert_eq!(acc.weights, vec![0.0, 1.0, 0.0, 0.5, 0.5, 1.0]);
+        let image = acc.average();
+        assert_eq!(image.get(1, 1), rgb!(1.0));
+        assert_eq!(image.get(2, 1), rgb!(0.0));
+
+        let mut whole = Accumulation::new(4, 3);
+        whole.merge(&acc, 1, 1);
+        whole.merge(&acc, 1, 1);
+        assert_eq!(whole.samples_at(2, 1), 2);
+        assert_eq!(whole.sums.get(2, 2), rgb!(1.0));
+        assert_eq!(whole.weights[2 * 4 + 2], 1.0);
+    }
+
+    #[test]
+    fn test_negative_lobe() {
+        let filter = Filter::new(FilterKind::Lanczos);
+        let mut acc = Accumulation::new(3, 1);
+        // 1.5 pixels from the center of pixel 0, on its negative lobe.
+        acc.add_sample(
+            &filter,
+            1,
+            0,
+            &FilmSample {
+                x: 2.0,
+                y: 0.5,
+                color: rgb!(1.0),
+            },
+        );
+        assert!(acc.weights[0] < 0.0);
+        let image = acc.average();
+        assert_eq!(image.get(0, 0), rgb!(0.0));
+        assert_eq!(image.get(1, 0), rgb!(1.0));
+    }
+
+    #[test]
+    fn test_sample_heatmap() {
+        let filter = Filter::default();
+        let mut acc = Accumulation::new(3, 1);
+        for n in 0..8 {
+            if n < 2 {
+                acc.add_sample(&filter, 1, 0, &sample(1, 0, rgb!(1.0)));
+            }
+            acc.add_sample(&filter, 2, 0, &sample(2, 0, rgb!(1.0)));
+        }
+        let heatmap = acc.sample_heatmap();
+        assert_eq!(heatmap.get(0, 0), rgb!(0.0));
+        assert_eq!(heatmap.get(1, 0), rgb!(0.0, 0.0, 1.0));
+        assert_eq!(heatmap.get(2, 0), rgb!(1.0));
+    }
+}
@@ -5,8 +5,9 @@ use crate::{
     interval::Interval,
     material::Material,
     math::{Point3, Vec2, Vec3},
-    object::{Hit, Object},
+    object::{area_to_solid_angle, Hit, Object},
     ray::Ray,
+    rng::Rng,
     util::EPSILON,
 };
 
@@ -20,6 +21,7 @@ fn quad_uv(a: f64, b: f64) -> Option<Vec2> {
     }
 }
 
+#[derive(Clone)]
 pub struct Quad {
     pub q: Point3,
     pub u: Vec3,
@@ -29,6 +31,7 @@ pub struct Quad {
     bbox: AABB,
     normal: Vec3,
     d: f64,
+    area: f64,
 }
 
 impl Quad {
@@ -48,12 +51,13 @@ impl Quad {
             bbox: bbox1 + bbox2,
             normal,
             d,
+            area: n.length(),
         }
     }
 }
 
 impl Object for Quad {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<Hit> {
+    fn hit(&self, r: &Ray, ray_t: &Interval, _rng: &mut Rng) -> Option<Hit> {
         let denom = self.normal.dot(&r.direction);
 
         if denom.abs() < EPSILON {
@@ -76,6 +80,29 @@ impl Object for Quad {
         }
     }
 
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64, rng: &mut Rng) -> f64 {
+        match self.hit(
+            &Ray::new(*origin, *direction, time),
+            &Interval::from(0.001),
+            rng,
+        ) {
+            Some(hit) => area_to_solid_angle(hit.t, direction, &self.normal, self.area),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, _time: f64, rng: &mut Rng) -> Vec3 {
+        let (a, b) = rng.get_2d();
+        let p = self.q + (a * self.u) + (b * self.v);
+        p - *origin
+    }
+
+    fn collect_lights(&self, lights: &mut Vec<Box<dyn Object>>) {
+        if self.mat.is_emissive() {
+            lights.push(Box::new(self.clone()));
+        }
+    }
+
     fn bbox(&self) -> &AABB {
         &self.bbox
     }
@@ -0,0 +1,34 @@
+use crate::sampler::Sampler;
+
+/// The source of random numbers threaded through rendering. Besides plain
+/// random numbers, it draws well spread values for the dimensions of a
+/// sample.
+pub type Rng = Sampler;
+
+// SplitMix64 finalizer, used to decorrelate nearby seeds.
+pub(crate) fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng as _;
+
+    use super::*;
+    use crate::sampler::SamplerKind;
+
+    #[test]
+    fn test_sample_rng() {
+        let rng = |seed, i, j, sample| {
+            Sampler::new(SamplerKind::Independent, seed, i, j, sample, 16).random::<u64>()
+        };
+        let a = rng(1, 2, 3, 4);
+        assert_eq!(a, rng(1, 2, 3, 4));
+        assert_ne!(a, rng(0, 2, 3, 4));
+        assert_ne!(a, rng(1, 3, 2, 4));
+        assert_ne!(a, rng(1, 2, 3, 5));
+    }
+}
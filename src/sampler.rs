@@ -0,0 +1,394 @@
+use std::str::FromStr;
+
+use rand::{rngs::SmallRng, Rng as _, RngCore, SeedableRng};
+
+use crate::{error::Error, rng::mix};
+
+/// Dimensions used by the camera: the pixel offset, the lens position and
+/// the time.
+pub const CAMERA_DIMENSIONS: u32 = 5;
+/// Dimensions set aside for every bounce of a path. Values drawn past them
+/// come from the plain generator.
+pub const BOUNCE_DIMENSIONS: u32 = 8;
+
+/// The sequences a [`Sampler`] draws its values from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// Independent uniform random values.
+    #[default]
+    Independent,
+    /// Jittered strata over the pixel's samples, shuffled per dimension.
+    Stratified,
+    /// The Halton sequence with its digits permuted per pixel.
+    Halton,
+    /// The Sobol sequence with Owen scrambling, shuffled per pair of
+    /// dimensions.
+    Sobol,
+}
+
+impl FromStr for SamplerKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "independent" => Ok(Self::Independent),
+            "stratified" => Ok(Self::Stratified),
+            "halton" => Ok(Self::Halton),
+            "sobol" => Ok(Self::Sobol),
+            _ => Err(Error::UnknownSampler(s.to_string())),
+        }
+    }
+}
+
+/// The source of randomness for one sample of a pixel.
+///
+/// [`get_1d`](Self::get_1d) and [`get_2d`](Self::get_2d) take the next
+/// dimensions of the sample from its sequence, so values of the same
+/// dimension are well spread over the samples of a pixel. The camera uses the
+/// first [`CAMERA_DIMENSIONS`], then every bounce starts at its own offset, so
+/// a dimension always feeds the same decision. The sampler is also a plain
+/// random number generator for everything else.
+pub struct Sampler {
+    kind: SamplerKind,
+    rng: SmallRng,
+    // Hash of the seed and the pixel, scrambling the sequences per pixel.
+    pixel: u64,
+    sample: u32,
+    samples: u32,
+    dimension: u32,
+    end: u32,
+}
+
+impl Sampler {
+    /// Returns the sampler for sample `sample` of `samples` of pixel `(i, j)`.
+    /// It depends only on its arguments, so renders are reproducible
+    /// regardless of which thread traces which pixel.
+    pub fn new(
+        kind: SamplerKind,
+        seed: u64,
+        i: usize,
+        j: usize,
+        sample: u32,
+        samples: u32,
+    ) -> Self {
+        let pixel = mix(mix(mix(seed) ^ i as u64) ^ j as u64);
+        Self {
+            kind,
+            rng: SmallRng::seed_from_u64(mix(pixel ^ sample as u64)),
+            pixel,
+            sample,
+            samples: samples.max(1),
+            dimension: 0,
+            end: CAMERA_DIMENSIONS,
+        }
+    }
+
+    /// Moves on to the dimensions of bounce `bounce` of the path, counting
+    /// the first hit as bounce 0.
+    pub fn start_bounce(&mut self, bounce: u32) {
+        self.dimension = CAMERA_DIMENSIONS.saturating_add(bounce.saturating_mul(BOUNCE_DIMENSIONS));
+        self.end = self.dimension.saturating_add(BOUNCE_DIMENSIONS);
+    }
+
+    /// The next value in [0, 1).
+    pub fn get_1d(&mut self) -> f64 {
+        let dimension = self.take(1);
+        match (self.kind, dimension) {
+            (_, None) | (SamplerKind::Independent, _) => self.rng.random(),
+            (SamplerKind::Stratified, Some(d)) => self.stratified(d, self.samples, 1).0,
+            (SamplerKind::Halton, Some(d)) => self.halton(d),
+            (SamplerKind::Sobol, Some(d)) => self.sobol(d).0,
+        }
+    }
+
+    /// The next point in [0, 1)².
+    pub fn get_2d(&mut self) -> (f64, f64) {
+        let dimension = self.take(2);
+        match (self.kind, dimension) {
+            (_, None) | (SamplerKind::Independent, _) => (self.rng.random(), self.rng.random()),
+            (SamplerKind::Stratified, Some(d)) => {
+                let nx = (self.samples as f64).sqrt().ceil() as u32;
+                let ny = self.samples.div_ceil(nx);
+                self.stratified(d, nx, ny)
+            }
+            (SamplerKind::Halton, Some(d)) => (self.halton(d), self.halton(d + 1)),
+            (SamplerKind::Sobol, Some(d)) => self.sobol(d),
+        }
+    }
+
+    // Claims the next `n` dimensions, if they fit before the end of the
+    // current block.
+    fn take(&mut self, n: u32) -> Option<u32> {
+        let d = self.dimension;
+        self.dimension = d.saturating_add(n);
+        (self.dimension <= self.end).then_some(d)
+    }
+
+    fn hash(&self, dimension: u32, salt: u64) -> u64 {
+        mix(self.pixel ^ mix(dimension as u64 | salt << 32))
+    }
+
+    // A jittered point in one of `nx * ny` cells. Each run of samples
+    // visits the cells in its own shuffled order.
+    fn stratified(&mut self, dimension: u32, nx: u32, ny: u32) -> (f64, f64) {
+        let cells = nx * ny;
+        let run = self.sample / self.samples;
+        let index = self.sample % self.samples;
+        let cell = permute(index, cells, self.hash(dimension, run as u64) as u32);
+        let x = (cell % nx) as f64 + self.rng.random::<f64>();
+        let y = (cell / nx) as f64 + self.rng.random::<f64>();
+        (
+            (x / nx as f64).min(ONE_MINUS_EPSILON),
+            (y / ny as f64).min(ONE_MINUS_EPSILON),
+        )
+    }
+
+    fn halton(&mut self, dimension: u32) -> f64 {
+        let Some(&base) = PRIMES.get(dimension as usize) else {
+            return self.rng.random();
+        };
+        scrambled_radical_inverse(base, self.sample, self.hash(dimension, 0))
+    }
+
+    fn sobol(&self, dimension: u32) -> (f64, f64) {
+        let seed = self.hash(dimension, 0);
+        let index = nested_uniform_scramble(self.sample, seed as u32);
+        let x = nested_uniform_scramble(index.reverse_bits(), (seed >> 32) as u32);
+        let y = nested_uniform_scramble(sobol_second(index), mix(seed) as u32);
+        (to_unit(x), to_unit(y))
+    }
+}
+
+impl RngCore for Sampler {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        self.rng.fill_bytes(dst)
+    }
+}
+
+/// Seeding gives an independent sampler, for use as a plain generator.
+impl SeedableRng for Sampler {
+    type Seed = <SmallRng as SeedableRng>::Seed;
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        let mut sampler = Self::new(SamplerKind::Independent, 0, 0, 0, 0, 1);
+        sampler.rng = SmallRng::from_seed(seed);
+        sampler
+    }
+
+    fn seed_from_u64(state: u64) -> Self {
+        let mut sampler = Self::new(SamplerKind::Independent, 0, 0, 0, 0, 1);
+        sampler.rng = SmallRng::seed_from_u64(state);
+        sampler
+    }
+}
+
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// The largest f64 below 1.
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// Maps 32 fixed-point bits to [0, 1).
+fn to_unit(x: u32) -> f64 {
+    x as f64 / (1u64 << 32) as f64
+}
+
+// Mirrors the digits of `index` in `base` around the radix point, permuting
+// every digit position on its own. Without that, dimensions with large bases
+// are strongly correlated over the first samples.
+fn scrambled_radical_inverse(base: u32, mut index: u32, seed: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut scale = inv_base;
+    let mut x = 0.0;
+    let mut position = 0;
+    // Zero digits past the last one are permuted too, down to where they
+    // no longer change the result.
+    while index > 0 || scale > f64::EPSILON {
+        let p = mix(seed ^ position) as u32;
+        x += permute(index % base, base, p) as f64 * scale;
+        index /= base;
+        scale *= inv_base;
+        position += 1;
+    }
+    x.min(ONE_MINUS_EPSILON)
+}
+
+// The second dimension of the Sobol sequence, whose generator matrix is the
+// upper triangular Pascal matrix.
+fn sobol_second(index: u32) -> u32 {
+    let mut x = 0;
+    let mut v = 1 << 31;
+    let mut index = index;
+    while index > 0 {
+        if index & 1 == 1 {
+            x ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    x
+}
+
+// Laine and Karras' hash, permuting `x` so that every bit depends only on the
+// bits below it.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+// Owen scrambling of a fixed-point fraction, after Burley's "Practical
+// Hash-based Owen Scrambling".
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Kensler's hashed permutation of `[0, len)`, from "Correlated Multi-Jittered
+// Sampling".
+fn permute(mut i: u32, len: u32, p: u32) -> u32 {
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % len
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The first 2D point of every sample of pixel (3, 4).
+    fn points(kind: SamplerKind, samples: u32) -> Vec<(f64, f64)> {
+        (0..samples)
+            .map(|sample| Sampler::new(kind, 1, 3, 4, sample, samples).get_2d())
+            .collect()
+    }
+
+    // Whether every cell of an `n` by `n` grid holds exactly one point.
+    fn stratifies(points: &[(f64, f64)], n: usize) -> bool {
+        let mut cells = vec![0; n * n];
+        for &(x, y) in points {
+            cells[(y * n as f64) as usize * n + (x * n as f64) as usize] += 1;
+        }
+        cells.iter().all(|&c| c == 1)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("sobol".parse::<SamplerKind>().unwrap(), SamplerKind::Sobol);
+        assert_eq!(
+            "halton".parse::<SamplerKind>().unwrap(),
+            SamplerKind::Halton
+        );
+        assert_eq!("Sobol".parse::<SamplerKind>().unwrap(), SamplerKind::Sobol);
+        assert!("blue".parse::<SamplerKind>().is_err());
+    }
+
+    #[test]
+    fn test_stratified() {
+        assert!(stratifies(&points(SamplerKind::Stratified, 16), 4));
+        assert!(stratifies(&points(SamplerKind::Sobol, 16), 4));
+        assert!(!stratifies(&points(SamplerKind::Independent, 16), 4));
+
+        let mut xs: Vec<usize> = (0..8)
+            .map(|sample| {
+                let mut sampler = Sampler::new(SamplerKind::Stratified, 1, 0, 0, sample, 8);
+                (sampler.get_1d() * 8.0) as usize
+            })
+            .collect();
+        xs.sort();
+        assert_eq!(xs, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_halton() {
+        // Scrambling keeps the first base^k values in distinct intervals of
+        // size 1 / base^k.
+        for (base, n) in [(2, 8), (3, 9), (17, 17)] {
+            let mut cells: Vec<u32> = (0..n)
+                .map(|i| (scrambled_radical_inverse(base, i, 42) * n as f64) as u32)
+                .collect();
+            cells.sort();
+            assert_eq!(cells, (0..n).collect::<Vec<_>>());
+        }
+        assert_eq!(sobol_second(1), 1 << 31);
+        assert_eq!(sobol_second(2), 3 << 30);
+    }
+
+    #[test]
+    fn test_reproducible() {
+        for kind in [
+            SamplerKind::Independent,
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let draw = |i| {
+                let mut sampler = Sampler::new(kind, 1, i, 0, 5, 16);
+                let a = sampler.get_2d();
+                sampler.start_bounce(2);
+                (a, sampler.get_1d(), sampler.random::<f64>())
+            };
+            assert_eq!(draw(0), draw(0));
+            assert_ne!(draw(0), draw(1));
+
+            let (a, b, _) = draw(0);
+            for x in [a.0, a.1, b] {
+                assert!((0.0..1.0).contains(&x));
+            }
+        }
+    }
+
+    #[test]
+    fn test_bounce_overflow() {
+        // Dimensions past a bounce's block don't repeat the next bounce's.
+        let mut a = Sampler::new(SamplerKind::Sobol, 1, 0, 0, 3, 16);
+        a.start_bounce(0);
+        for _ in 0..BOUNCE_DIMENSIONS / 2 {
+            a.get_2d();
+        }
+        let mut b = Sampler::new(SamplerKind::Sobol, 1, 0, 0, 3, 16);
+        b.start_bounce(1);
+        assert_ne!(a.get_2d(), b.get_2d());
+    }
+}
@@ -3,16 +3,42 @@ use crate::{
     camera::Camera,
     color::Color,
     error::Error,
+    film::Film,
     interval::Interval,
-    object::Object,
+    math::{Point3, Vec3},
+    object::{Hit, Object},
     ray::Ray,
     rgb,
+    rng::Rng,
+    sampler::Sampler,
+    stats,
+    util::power_heuristic,
 };
 
+/// One sample of the film.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilmSample {
+    /// Where the sample was taken, in pixels from the top left corner of the
+    /// image.
+    pub x: f64,
+    pub y: f64,
+    /// The linear radiance found.
+    pub color: Color,
+}
+
+/// A loaded scene: the camera, the object hierarchy and the background seen
+/// by rays that escape it.
 pub struct Scene {
     pub camera: Camera,
     pub world: Box<dyn Object>,
     pub background: Box<dyn Background>,
+    pub seed: u64,
+    /// Identifies the scene description a render was started from, see
+    /// [`Checkpoint`](crate::checkpoint::Checkpoint).
+    pub hash: u64,
+    pub film: Film,
+    /// Emissive primitives sampled directly at every diffuse bounce.
+    pub lights: Vec<Box<dyn Object>>,
 }
 
 impl Scene {
@@ -21,48 +47,128 @@ impl Scene {
         world: Box<dyn Object>,
         bg: Option<Box<dyn Background>>,
     ) -> Result<Self, Error> {
+        let mut lights = vec![];
+        world.collect_lights(&mut lights);
+
         Ok(Self {
             camera,
             world,
+            lights,
             background: bg.unwrap_or_else(|| Box::new(Gradient::default())),
+            seed: 0,
+            hash: 0,
+            film: Film::default(),
         })
     }
 
-    pub fn render(&self, i: usize, j: usize) -> (u8, u8, u8) {
-        let mut pixel_color = rgb!(0.0, 0.0, 0.0);
-        for _ in 0..self.camera.samples {
-            let r = self.camera.get_ray(i, j);
-            pixel_color += self.ray_color(&r, self.camera.max_depth);
+    /// Returns the averaged linear radiance of the samples taken in pixel
+    /// `(i, j)`, without filtering.
+    pub fn render(&self, i: usize, j: usize) -> Color {
+        let mut color = rgb!(0.0);
+        for sample in 0..self.camera.samples {
+            color += self.render_sample(i, j, sample).color;
         }
-        (pixel_color * self.camera.samples_scale).to_pixel()
+        color * self.camera.samples_scale
     }
 
-    fn ray_color(&self, r: &Ray, depth: u32) -> Color {
+    /// Renders sample `sample` of pixel `(i, j)`. Every sample is seeded on
+    /// its own, so samples can be rendered in any order.
+    pub fn render_sample(&self, i: usize, j: usize, sample: u32) -> FilmSample {
+        let mut rng = Sampler::new(
+            self.camera.sampler,
+            self.seed,
+            i,
+            j,
+            sample,
+            self.camera.samples,
+        );
+        let (dx, dy) = rng.get_2d();
+        let (x, y) = (i as f64 + dx, j as f64 + dy);
+        let color = match self.camera.get_ray_at(x, y, &mut rng) {
+            Some(r) => self.ray_color(&r, self.camera.max_depth, None, &mut rng),
+            None => rgb!(0.0, 0.0, 0.0),
+        };
+        FilmSample { x, y, color }
+    }
+
+    // `bsdf_pdf` is the density with which the previous bounce picked `r`, if
+    // that bounce also sampled the lights directly. Emission found this way
+    // is then weighted against the light sample with the power heuristic.
+    fn ray_color(&self, r: &Ray, depth: u32, bsdf_pdf: Option<f64>, rng: &mut Rng) -> Color {
         if depth == 0 {
             return rgb!(0.0, 0.0, 0.0);
         }
 
-        if let Some(hit) = self.world.hit(r, &Interval::from(0.001)) {
-            let emitted = hit.mat.emitted(r, &hit);
-            if let Some(scatter) = hit.mat.scatter(r, &hit) {
-                return emitted + scatter.att * self.ray_color(&scatter.ray, depth - 1);
+        stats::record(|c| {
+            if depth == self.camera.max_depth {
+                c.primary_rays += 1
             } else {
-                return emitted;
+                c.bounce_rays += 1
+            }
+        });
+        let Some(hit) = self.world.hit(r, &Interval::from(0.001), rng) else {
+            let dir = r.direction.unit();
+            return self.background.sample_bg(&dir);
+        };
+        rng.start_bounce(self.camera.max_depth - depth);
+
+        let mut emitted = hit.mat.emitted(r, &hit);
+        if let Some(bsdf_pdf) = bsdf_pdf {
+            if emitted != rgb!(0.0) {
+                let light_pdf = self.light_pdf(&r.origin, &r.direction, r.time, rng);
+                emitted *= power_heuristic(bsdf_pdf, light_pdf);
             }
         }
 
-        let dir = r.direction.unit();
-        self.background.sample_bg(&dir)
+        let Some(scatter) = hit.mat.scatter(r, &hit, rng) else {
+            return emitted;
+        };
+
+        if scatter.specular || self.lights.is_empty() {
+            return emitted + scatter.att * self.ray_color(&scatter.ray, depth - 1, None, rng);
+        }
+
+        emitted
+            + self.sample_light(r, &hit, rng)
+            + scatter.att * self.ray_color(&scatter.ray, depth - 1, Some(scatter.pdf), rng)
     }
 
-    pub fn write_image_header(&self) {
-        println!(
-            "P3\n{} {}\n255",
-            self.camera.image_width, self.camera.image_height
-        );
+    // The density of picking `direction` from `origin` by choosing one of the
+    // lights uniformly and sampling it.
+    fn light_pdf(&self, origin: &Point3, direction: &Vec3, time: f64, rng: &mut Rng) -> f64 {
+        let sum: f64 = self
+            .lights
+            .iter()
+            .map(|light| light.pdf_value(origin, direction, time, rng))
+            .sum();
+        sum / self.lights.len() as f64
     }
 
-    pub fn write_pixel(&self, color: (u8, u8, u8)) {
-        println!("{} {} {}", color.0, color.1, color.2);
+    // Direct lighting from a single light sample, weighted against the BSDF
+    // sample with the power heuristic.
+    fn sample_light(&self, r: &Ray, hit: &Hit, rng: &mut Rng) -> Color {
+        let n = self.lights.len();
+        let index = ((rng.get_1d() * n as f64) as usize).min(n - 1);
+        let direction = self.lights[index].random(&hit.p, r.time, rng);
+        let light_pdf = self.light_pdf(&hit.p, &direction, r.time, rng);
+        if light_pdf <= 0.0 {
+            return rgb!(0.0);
+        }
+
+        let shadow_ray = Ray::new(hit.p, direction, r.time);
+        let bsdf_pdf = hit.mat.pdf(r, hit, &shadow_ray);
+        if bsdf_pdf <= 0.0 {
+            return rgb!(0.0);
+        }
+
+        stats::record(|c| c.shadow_rays += 1);
+        let Some(light_hit) = self.world.hit(&shadow_ray, &Interval::from(0.001), rng) else {
+            return rgb!(0.0);
+        };
+        let emitted = light_hit.mat.emitted(&shadow_ray, &light_hit);
+
+        let f = hit.mat.eval(r, hit, &shadow_ray);
+
+        f * emitted * power_heuristic(light_pdf, bsdf_pdf) / light_pdf
     }
 }
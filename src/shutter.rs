@@ -0,0 +1,122 @@
+use std::str::FromStr;
+
+use crate::error::Error;
+
+/// How the exposure is spread over the time the shutter is open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShutterCurve {
+    /// Every moment counts the same.
+    #[default]
+    Box,
+    /// The shutter opens and closes gradually, exposing the middle the most.
+    Triangle,
+    /// The scanlines are exposed one after another, from the top down.
+    Rolling,
+}
+
+impl FromStr for ShutterCurve {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "box" => Ok(Self::Box),
+            "triangle" | "tent" => Ok(Self::Triangle),
+            "rolling" => Ok(Self::Rolling),
+            _ => Err(Error::UnknownShutterCurve(s.to_string())),
+        }
+    }
+}
+
+/// When rays leave the camera. Moving objects are placed by the time of the
+/// ray, so these are the same units as their motion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shutter {
+    pub open: f64,
+    pub close: f64,
+    pub curve: ShutterCurve,
+    /// For a rolling shutter, the part of the interval each scanline is
+    /// exposed for. The rest is how long the shutter takes to pass down the
+    /// image.
+    pub rolling_exposure: f64,
+}
+
+impl Default for Shutter {
+    fn default() -> Self {
+        Self {
+            open: 0.0,
+            close: 1.0,
+            curve: ShutterCurve::default(),
+            rolling_exposure: 0.0,
+        }
+    }
+}
+
+impl Shutter {
+    /// The time of a ray for the uniform sample `u`, through a point `row`
+    /// of the way down the image.
+    pub fn time(&self, u: f64, row: f64) -> f64 {
+        let t = match self.curve {
+            ShutterCurve::Box => u,
+            ShutterCurve::Triangle => {
+                if u < 0.5 {
+                    (2.0 * u).sqrt() / 2.0
+                } else {
+                    1.0 - (2.0 * (1.0 - u)).sqrt() / 2.0
+                }
+            }
+            ShutterCurve::Rolling => {
+                let exposure = self.rolling_exposure;
+                row.clamp(0.0, 1.0) * (1.0 - exposure) + u * exposure
+            }
+        };
+        self.open + t * (self.close - self.open)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assert_in_delta;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "Rolling".parse::<ShutterCurve>().unwrap(),
+            ShutterCurve::Rolling
+        );
+        assert!("iris".parse::<ShutterCurve>().is_err());
+    }
+
+    #[test]
+    fn test_time() {
+        let shutter = Shutter {
+            open: 2.0,
+            close: 4.0,
+            ..Default::default()
+        };
+        assert_eq!(shutter.time(0.0, 0.3), 2.0);
+        assert_eq!(shutter.time(0.25, 0.3), 2.5);
+
+        let shutter = Shutter {
+            curve: ShutterCurve::Triangle,
+            ..shutter
+        };
+        assert_eq!(shutter.time(0.0, 0.3), 2.0);
+        assert_eq!(shutter.time(0.5, 0.3), 3.0);
+        assert_in_delta!(shutter.time(1.0, 0.3), 4.0);
+        // Only an eighth of the exposure is over after a quarter of the
+        // interval, 2 * (1/4)^2.
+        assert_in_delta!(shutter.time(0.125, 0.3), 2.5);
+
+        let shutter = Shutter {
+            curve: ShutterCurve::Rolling,
+            rolling_exposure: 0.5,
+            ..shutter
+        };
+        assert_eq!(shutter.time(0.0, 0.0), 2.0);
+        assert_eq!(shutter.time(1.0, 0.0), 3.0);
+        assert_eq!(shutter.time(0.0, 1.0), 3.0);
+        assert_eq!(shutter.time(1.0, 1.0), 4.0);
+    }
+}
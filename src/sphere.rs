@@ -1,18 +1,36 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 use crate::{
     aabb::AABB,
     interval::Interval,
     material::Material,
-    math::Vec3,
-    object::{Hit, Object},
+    math::{Point3, Vec3},
+    object::{area_to_solid_angle, Hit, Object},
+    onb::Onb,
     ray::Ray,
+    rng::Rng,
     util::sphere_uv,
     vec3,
 };
 
+// A random direction towards a sphere of `radius` at `distance_squared`, with
+// the sphere centered on the z axis.
+fn random_to_sphere(rng: &mut Rng, radius: f64, distance_squared: f64) -> Vec3 {
+    let (r1, r2) = rng.get_2d();
+    let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+
+    vec3!(x, y, z)
+}
+
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Ray,
+    // When the center is at the start and the end of `center`.
+    times: Interval,
     pub radius: f64,
     pub mat: Arc<dyn Material>,
     bbox: AABB,
@@ -23,28 +41,52 @@ impl Sphere {
         let rvec = vec3!(radius, radius, radius);
         Self {
             center: Ray::new(center, vec3!(0.0, 0.0, 0.0), 0.0),
+            times: Interval::new(0.0, 1.0),
             radius,
             mat: Arc::clone(mat),
             bbox: AABB::from_points(center - rvec, center + rvec),
         }
     }
 
+    /// A sphere moving from `center1` at time 0 to `center2` at time 1.
     pub fn moving(center1: Vec3, center2: Vec3, radius: f64, mat: &Arc<dyn Material>) -> Self {
+        Self::moving_between(center1, 0.0, center2, 1.0, radius, mat)
+    }
+
+    /// A sphere moving steadily from `center1` at `time1` to `center2` at
+    /// `time2`, and resting there before and after.
+    pub fn moving_between(
+        center1: Vec3,
+        time1: f64,
+        center2: Vec3,
+        time2: f64,
+        radius: f64,
+        mat: &Arc<dyn Material>,
+    ) -> Self {
         let rvec = vec3!(radius, radius, radius);
         let center = Ray::new(center1, center2 - center1, 0.0);
         Self {
             center,
+            times: Interval::new(time1, time2),
             radius,
             mat: Arc::clone(mat),
             bbox: AABB::from_points(center.at(0.0) - rvec, center.at(0.0) + rvec)
                 + AABB::from_points(center.at(1.0) - rvec, center.at(1.0) + rvec),
         }
     }
+
+    fn center_at(&self, time: f64) -> Point3 {
+        if self.times.size() <= 0.0 {
+            return self.center.at(0.0);
+        }
+        let t = (self.times.clamp(time) - self.times.min) / self.times.size();
+        self.center.at(t)
+    }
 }
 
 impl Object for Sphere {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<Hit> {
-        let center = self.center.at(r.time);
+    fn hit(&self, r: &Ray, ray_t: &Interval, _rng: &mut Rng) -> Option<Hit> {
+        let center = self.center_at(r.time);
         let oc = center - r.origin;
         let a = r.direction.length_squared();
         let h = r.direction.dot(&oc);
@@ -75,4 +117,70 @@ impl Object for Sphere {
     fn bbox(&self) -> &AABB {
         &self.bbox
     }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64, rng: &mut Rng) -> f64 {
+        let Some(hit) = self.hit(
+            &Ray::new(*origin, *direction, time),
+            &Interval::from(0.001),
+            rng,
+        ) else {
+            return 0.0;
+        };
+
+        let distance_squared = (self.center_at(time) - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            // From inside the sphere we sample its whole surface by area.
+            let area = 4.0 * PI * radius_squared;
+            return area_to_solid_angle(hit.t, direction, &hit.normal, area);
+        }
+
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, time: f64, rng: &mut Rng) -> Vec3 {
+        let center = self.center_at(time);
+        let direction = center - *origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return center + self.radius * Vec3::random_unit(rng) - *origin;
+        }
+
+        let uvw = Onb::new(&direction);
+        uvw.transform(&random_to_sphere(rng, self.radius, distance_squared))
+    }
+
+    fn collect_lights(&self, lights: &mut Vec<Box<dyn Object>>) {
+        if self.mat.is_emissive() {
+            lights.push(Box::new(self.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{color::Color, lambertian::Lambertian, point, rgb};
+
+    use super::*;
+
+    #[test]
+    fn test_moving_between() {
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::solid(rgb!(0.5)));
+        let sphere = Sphere::moving_between(
+            point!(0.0, 0.0, 0.0),
+            2.0,
+            point!(4.0, 0.0, 0.0),
+            4.0,
+            1.0,
+            &mat,
+        );
+        assert_eq!(sphere.center_at(3.0), point!(2.0, 0.0, 0.0));
+        // Resting before and after the motion.
+        assert_eq!(sphere.center_at(0.0), point!(0.0, 0.0, 0.0));
+        assert_eq!(sphere.center_at(5.0), point!(4.0, 0.0, 0.0));
+        assert_eq!(sphere.bbox().x, Interval::new(-1.0, 5.0));
+    }
 }
@@ -0,0 +1,211 @@
+use std::{cell::Cell, fmt, ops, time::Duration};
+
+/// Work counted while loading and rendering a scene.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Counters {
+    pub primary_rays: u64,
+    /// Rays continuing a path after a bounce.
+    pub bounce_rays: u64,
+    /// Rays towards light samples.
+    pub shadow_rays: u64,
+    pub bvh_nodes: u64,
+    pub primitives: u64,
+    pub bvh_build: Duration,
+}
+
+impl Counters {
+    const ZERO: Self = Self {
+        primary_rays: 0,
+        bounce_rays: 0,
+        shadow_rays: 0,
+        bvh_nodes: 0,
+        primitives: 0,
+        bvh_build: Duration::ZERO,
+    };
+
+    pub fn rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays()
+    }
+
+    pub fn secondary_rays(&self) -> u64 {
+        self.bounce_rays + self.shadow_rays
+    }
+}
+
+impl ops::AddAssign for Counters {
+    fn add_assign(&mut self, rhs: Self) {
+        self.primary_rays += rhs.primary_rays;
+        self.bounce_rays += rhs.bounce_rays;
+        self.shadow_rays += rhs.shadow_rays;
+        self.bvh_nodes += rhs.bvh_nodes;
+        self.primitives += rhs.primitives;
+        self.bvh_build += rhs.bvh_build;
+    }
+}
+
+thread_local! {
+    static COUNTERS: Cell<Counters> = const { Cell::new(Counters::ZERO) };
+}
+
+/// Adds to the counters of the current thread.
+pub fn record(f: impl FnOnce(&mut Counters)) {
+    COUNTERS.with(|c| {
+        let mut counters = c.get();
+        f(&mut counters);
+        c.set(counters);
+    });
+}
+
+/// Returns the counters of the current thread and resets them.
+pub fn take() -> Counters {
+    COUNTERS.with(|c| c.replace(Counters::ZERO))
+}
+
+/// Timings and counters of a whole run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderStats {
+    pub wall: Duration,
+    /// Time spent loading the scene, including building its BVHs.
+    pub load: Duration,
+    pub render: Duration,
+    pub threads: usize,
+    pub pixels: usize,
+    pub counters: Counters,
+}
+
+fn per(n: u64, d: u64) -> f64 {
+    if d == 0 {
+        0.0
+    } else {
+        n as f64 / d as f64
+    }
+}
+
+impl RenderStats {
+    pub fn rays_per_second(&self) -> f64 {
+        let secs = self.render.as_secs_f64();
+        if secs > 0.0 {
+            self.counters.rays() as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Segments per camera path, counting the camera ray but not shadow rays.
+    pub fn average_path_length(&self) -> f64 {
+        let c = &self.counters;
+        per(c.primary_rays + c.bounce_rays, c.primary_rays)
+    }
+
+    pub fn nodes_per_ray(&self) -> f64 {
+        per(self.counters.bvh_nodes, self.counters.rays())
+    }
+
+    pub fn primitives_per_ray(&self) -> f64 {
+        per(self.counters.primitives, self.counters.rays())
+    }
+
+    /// The stats as a flat JSON object, times in seconds.
+    pub fn to_json(&self) -> String {
+        let c = &self.counters;
+        let fields = [
+            ("wall_time", self.wall.as_secs_f64().to_string()),
+            ("load_time", self.load.as_secs_f64().to_string()),
+            ("bvh_build_time", c.bvh_build.as_secs_f64().to_string()),
+            ("render_time", self.render.as_secs_f64().to_string()),
+            ("threads", self.threads.to_string()),
+            ("pixels", self.pixels.to_string()),
+            ("primary_rays", c.primary_rays.to_string()),
+            ("secondary_rays", c.secondary_rays().to_string()),
+            ("bounce_rays", c.bounce_rays.to_string()),
+            ("shadow_rays", c.shadow_rays.to_string()),
+            ("rays_per_second", self.rays_per_second().to_string()),
+            (
+                "average_path_length",
+                self.average_path_length().to_string(),
+            ),
+            ("bvh_nodes_per_ray", self.nodes_per_ray().to_string()),
+            ("primitives_per_ray", self.primitives_per_ray().to_string()),
+        ];
+        let body: Vec<String> = fields
+            .iter()
+            .map(|(k, v)| format!("  \"{}\": {}", k, v))
+            .collect();
+        format!("{{\n{}\n}}\n", body.join(",\n"))
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = &self.counters;
+        writeln!(f, "Wall time:          {:.3}s", self.wall.as_secs_f64())?;
+        writeln!(
+            f,
+            "  Scene loading:    {:.3}s (BVH build {:.3}s)",
+            self.load.as_secs_f64(),
+            c.bvh_build.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "  Rendering:        {:.3}s on {} thread{}",
+            self.render.as_secs_f64(),
+            self.threads,
+            if self.threads == 1 { "" } else { "s" }
+        )?;
+        writeln!(f, "Pixels:             {}", self.pixels)?;
+        writeln!(f, "Primary rays:       {}", c.primary_rays)?;
+        writeln!(
+            f,
+            "Secondary rays:     {} ({} bounce, {} shadow)",
+            c.secondary_rays(),
+            c.bounce_rays,
+            c.shadow_rays
+        )?;
+        writeln!(
+            f,
+            "Rays per second:    {:.3}M",
+            self.rays_per_second() / 1e6
+        )?;
+        writeln!(f, "Avg path length:    {:.2}", self.average_path_length())?;
+        writeln!(f, "BVH nodes per ray:  {:.2}", self.nodes_per_ray())?;
+        write!(f, "Primitives per ray: {:.2}", self.primitives_per_ray())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_take_resets() {
+        take();
+        record(|c| c.primary_rays += 2);
+        record(|c| c.shadow_rays += 1);
+        let counters = take();
+        assert_eq!(counters.primary_rays, 2);
+        assert_eq!(counters.rays(), 3);
+        assert_eq!(take(), Counters::default());
+    }
+
+    #[test]
+    fn test_ratios() {
+        let stats = RenderStats {
+            render: Duration::from_secs(2),
+            counters: Counters {
+                primary_rays: 10,
+                bounce_rays: 20,
+                shadow_rays: 10,
+                bvh_nodes: 400,
+                primitives: 80,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(stats.rays_per_second(), 20.0);
+        assert_eq!(stats.average_path_length(), 3.0);
+        assert_eq!(stats.nodes_per_ray(), 10.0);
+        assert_eq!(stats.primitives_per_ray(), 2.0);
+        assert!(stats.to_json().contains("\"primary_rays\": 10,"));
+        assert_eq!(RenderStats::default().average_path_length(), 0.0);
+    }
+}
@@ -11,6 +11,54 @@ macro_rules! assert_in_delta {
         )
     };
     ($left:expr, $right:expr) => {
-        assert_in_delta!($left, $right, crate::util::EPSILON)
+        $crate::assert_in_delta!($left, $right, $crate::util::EPSILON)
     };
 }
+
+/// A diffuse sphere lit by a quad light above it, seen by a `width` by
+/// `height` camera taking `samples` samples per pixel.
+#[cfg(test)]
+pub fn scene(width: usize, height: usize, samples: u32) -> crate::scene::Scene {
+    use std::sync::Arc;
+
+    use crate::{
+        camera::Camera,
+        color::Color,
+        diffuse_light::DiffuseLight,
+        group::Group,
+        lambertian::Lambertian,
+        material::Material,
+        math::{Point3, Vec3},
+        point,
+        quad::Quad,
+        rgb,
+        scene::Scene,
+        sphere::Sphere,
+        vec3,
+    };
+
+    let diffuse: Arc<dyn Material> = Arc::new(Lambertian::solid(rgb!(0.5)));
+    let light: Arc<dyn Material> = Arc::new(DiffuseLight::solid(rgb!(4.0)));
+    let world = Group::new(vec![
+        Box::new(Sphere::stationary(point!(0.0, 0.0, -1.0), 0.5, &diffuse)),
+        Box::new(Quad::new(
+            point!(-0.5, 1.0, -1.5),
+            vec3!(1.0, 0.0, 0.0),
+            vec3!(0.0, 0.0, 1.0),
+            &light,
+        )),
+    ]);
+    let camera = Camera::new(
+        width,
+        height,
+        90.0,
+        point!(0.0, 0.0, 0.0),
+        point!(0.0, 0.0, -1.0),
+        vec3!(0.0, 1.0, 0.0),
+        0.0,
+        1.0,
+        samples,
+        4,
+    );
+    Scene::new(camera, Box::new(world), None).unwrap()
+}
@@ -3,6 +3,7 @@ use crate::{
     math::{Point3, Vec2},
 };
 
+/// A color that varies over surface coordinates or space.
 pub trait Texture: Send + Sync {
     fn sample_tex(&self, uv: &Vec2, p: &Point3) -> Color;
 }
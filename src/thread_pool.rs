@@ -1,87 +1,309 @@
 use std::{
-    sync::{mpsc, Arc, Mutex},
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
     thread,
 };
 
-use crate::scene::Scene;
-
-pub fn render_threaded(size: usize, scene: &Scene) {
-    eprintln!("RUNNING ON {} CPUS", size);
-    let (tx, rx) = mpsc::channel::<usize>();
-    let (result_tx, result_rx) = mpsc::channel::<(usize, Vec<(u8, u8, u8)>)>();
-    let rx = Arc::new(Mutex::new(rx));
-    let result_tx = Arc::new(Mutex::new(result_tx));
-
-    let mut image: Vec<(u8, u8, u8)> =
-        vec![(0, 0, 0); scene.camera.image_width * scene.camera.image_height];
-
-    scene.write_image_header();
-
-    thread::scope(|s| {
-        for _ in 0..size {
-            let rx = Arc::clone(&rx);
-            let result_tx = Arc::clone(&result_tx);
-            s.spawn(move || loop {
-                let msg = rx.lock().unwrap().recv();
-
-                match msg {
-                    Ok(j) => {
-                        let row: Vec<(u8, u8, u8)> = (0..scene.camera.image_width)
-                            .into_iter()
-                            .map(|i| scene.render(i, j))
-                            .collect();
-
-                        result_tx
-                            .lock()
-                            .unwrap()
-                            .send((j, row))
-                            .expect("Failed to send pixel");
-                    }
-                    Err(_) => {
-                        break;
-                    }
-                }
-            });
+use crate::{
+    output::FrameBuffer,
+    progress::{ProgressReporter, TerminalProgress},
+    progressive::Accumulation,
+    scene::Scene,
+    stats::{self, Counters},
+    tile::{Tile, TileOrder},
+};
+
+/// How a frame is split up and spread over threads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    pub threads: usize,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            threads: num_cpus::get(),
+            tile_size: 16,
+            tile_order: TileOrder::default(),
         }
+    }
+}
 
-        for j in 0..scene.camera.image_height {
-            tx.send(j).expect("Failed to send pixel");
+// Each worker owns a queue of tiles. It takes work from the front of its own
+// queue and, once that is empty, steals from the back of the others.
+struct TileQueues {
+    queues: Vec<Mutex<VecDeque<Tile>>>,
+}
+
+impl TileQueues {
+    fn new(tiles: Vec<Tile>, workers: usize) -> Self {
+        let mut queues = vec![VecDeque::new(); workers];
+        // Dealing the tiles out in turn keeps every worker close to the
+        // requested order.
+        for (n, tile) in tiles.into_iter().enumerate() {
+            queues[n % workers].push_back(tile);
+        }
+        Self {
+            queues: queues.into_iter().map(Mutex::new).collect(),
         }
+    }
 
-        drop(tx);
+    fn next(&self, worker: usize) -> Option<Tile> {
+        if let Some(tile) = self.queues[worker].lock().unwrap().pop_front() {
+            return Some(tile);
+        }
 
-        for j in 0..scene.camera.image_height {
-            eprint!(
-                "\rProgress: {}% ",
-                (((j as f64) / (scene.camera.image_height as f64)) * 100.0) as u8
-            );
-            let (j, row) = result_rx.recv().expect("Failed to receive pixel");
-            let row_start = j * scene.camera.image_width;
-            image[row_start..row_start + scene.camera.image_width].copy_from_slice(&row);
+        let n = self.queues.len();
+        (1..n).find_map(|k| self.queues[(worker + k) % n].lock().unwrap().pop_back())
+    }
+}
+
+// The tiles a worker rendered, each with the new samples splatted into the
+// tile and the margin around it reached by the filter.
+type Finished = Vec<(Tile, Window)>;
+
+// A part of the image, with its top left pixel.
+struct Window {
+    x: usize,
+    y: usize,
+    acc: Accumulation,
+}
+
+impl Window {
+    fn around(tile: &Tile, margin: usize, width: usize, height: usize) -> Self {
+        let (x, y) = (tile.x.saturating_sub(margin), tile.y.saturating_sub(margin));
+        let x1 = (tile.x + tile.width + margin).min(width);
+        let y1 = (tile.y + tile.height + margin).min(height);
+        Self {
+            x,
+            y,
+            acc: Accumulation::new(x1 - x, y1 - y),
         }
+    }
+}
 
-        drop(result_rx);
-    });
+// Progress shared by the workers of every pass of a render.
+pub(crate) struct PassProgress<'a> {
+    pub done: AtomicU64,
+    pub total: u64,
+    pub reporter: &'a dyn ProgressReporter,
+}
+
+// Renders tiles until there are none left, keeping the results and counters
+// local to the worker until it is done.
+fn work(
+    worker: usize,
+    scene: &Scene,
+    acc: &Accumulation,
+    plan: &[u32],
+    queues: &TileQueues,
+    progress: &PassProgress,
+) -> (Finished, Counters) {
+    let mut finished = vec![];
+    let before = stats::take();
+
+    let filter = &scene.film.filter;
+    while let Some(tile) = queues.next(worker) {
+        let mut window = Window::around(&tile, filter.margin(), acc.sums.width, acc.sums.height);
+        let mut units = 0;
+        for (i, j) in tile.pixels() {
+            let from = acc.samples_at(i, j);
+            let n = plan[j * acc.sums.width + i];
+            for sample in from..from + n {
+                let mut s = scene.render_sample(i, j, sample);
+                s.x -= window.x as f64;
+                s.y -= window.y as f64;
+                window
+                    .acc
+                    .add_sample(filter, i - window.x, j - window.y, &s);
+            }
+            units += n as u64;
+        }
 
-    eprintln!("\rDone.                   ");
+        let done = progress.done.fetch_add(units, Ordering::Relaxed) + units;
+        progress.reporter.progress(done, progress.total);
 
-    for color in image {
-        scene.write_pixel(color);
+        finished.push((tile, window));
     }
+
+    let counters = stats::take();
+    stats::record(|c| *c = before);
+    (finished, counters)
 }
 
-pub fn render_unthreaded(scene: &Scene) {
-    scene.write_image_header();
+// Adds `plan[p]` samples to every pixel `p` of `acc`.
+pub(crate) fn render_pass(
+    scene: &Scene,
+    options: &RenderOptions,
+    acc: &mut Accumulation,
+    plan: &[u32],
+    progress: &PassProgress,
+) -> Counters {
+    let workers = options.threads.max(1);
+    let tiles = Tile::split(
+        acc.sums.width,
+        acc.sums.height,
+        options.tile_size,
+        options.tile_order,
+    );
+    let queues = TileQueues::new(tiles, workers);
+
+    let results: Vec<(Finished, Counters)> = {
+        let acc = &*acc;
+        if workers == 1 {
+            vec![work(0, scene, acc, plan, &queues, progress)]
+        } else {
+            thread::scope(|s| {
+                let handles: Vec<_> = (0..workers)
+                    .map(|worker| {
+                        let queues = &queues;
+                        s.spawn(move || work(worker, scene, acc, plan, queues, progress))
+                    })
+                    .collect();
 
-    for j in 0..scene.camera.image_height {
-        for i in 0..scene.camera.image_width {
-            eprint!(
-                "\rProgress: {}% ",
-                (((j as f64) / (scene.camera.image_height as f64)) * 100.0) as u8
-            );
-            scene.write_pixel(scene.render(i, j));
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("Render thread panicked"))
+                    .collect()
+            })
         }
+    };
+
+    // Neighbouring tiles overlap where the filter reaches across, so they are
+    // merged in a fixed order to keep the sums independent of the threads.
+    let mut counters = Counters::default();
+    let mut windows = vec![];
+    for (tiles, worker_counters) in results {
+        windows.extend(tiles);
+        counters += worker_counters;
+    }
+    windows.sort_by_key(|(tile, _)| (tile.y, tile.x));
+    for (_, window) in windows {
+        acc.merge(&window.acc, window.x, window.y);
+    }
+    counters
+}
+
+/// Renders `scene` with the given options. A single thread renders on the
+/// calling thread.
+pub fn render(scene: &Scene, options: &RenderOptions) -> FrameBuffer {
+    render_with_stats(scene, options, &TerminalProgress::default()).0
+}
+
+/// Like [`render`], reporting progress to `progress` and also returning the
+/// work done by all threads.
+pub fn render_with_stats(
+    scene: &Scene,
+    options: &RenderOptions,
+    progress: &dyn ProgressReporter,
+) -> (FrameBuffer, Counters) {
+    let camera = &scene.camera;
+    let mut acc = Accumulation::new(camera.image_width, camera.image_height);
+    let plan = vec![camera.samples; camera.image_width * camera.image_height];
+    let pass_progress = PassProgress {
+        done: AtomicU64::new(0),
+        total: (camera.image_width * camera.image_height) as u64 * camera.samples as u64,
+        reporter: progress,
+    };
+
+    progress.start(pass_progress.total, 0, options.threads.max(1));
+    let counters = render_pass(scene, options, &mut acc, &plan, &pass_progress);
+    progress.finish();
+
+    (acc.average(), counters)
+}
+
+/// Renders `scene` on `size` threads.
+pub fn render_threaded(size: usize, scene: &Scene) -> FrameBuffer {
+    render(
+        scene,
+        &RenderOptions {
+            threads: size,
+            ..Default::default()
+        },
+    )
+}
+
+/// Renders `scene` on the calling thread.
+pub fn render_unthreaded(scene: &Scene) -> FrameBuffer {
+    render_threaded(1, scene)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        filter::{Filter, FilterKind},
+        test_data,
+    };
+
+    use crate::progress::QuietProgress;
+
+    use super::*;
+
+    #[test]
+    fn test_render_independent_of_threads() {
+        let mut scene = test_data::scene(13, 9, 4);
+        for filter in [Filter::default(), Filter::new(FilterKind::Mitchell)] {
+            scene.film.filter = filter;
+            let expected = render_unthreaded(&scene).to_rgb32f();
+            for tile_order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+                let options = RenderOptions {
+                    threads: 3,
+                    tile_size: 4,
+                    tile_order,
+                };
+                assert_eq!(render(&scene, &options).to_rgb32f(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_counters_independent_of_threads() {
+        let scene = test_data::scene(13, 9, 4);
+        let (_, expected) = render_with_stats(
+            &scene,
+            &RenderOptions {
+                threads: 1,
+                ..Default::default()
+            },
+            &QuietProgress,
+        );
+        assert_eq!(expected.primary_rays, 13 * 9 * 4);
+        assert!(expected.shadow_rays > 0);
+
+        let (_, counters) = render_with_stats(
+            &scene,
+            &RenderOptions {
+                threads: 3,
+                tile_size: 4,
+                ..Default::default()
+            },
+            &QuietProgress,
+        );
+        assert_eq!(counters, expected);
     }
 
-    eprintln!("\rDone.                   ");
+    #[test]
+    fn test_progress_callback() {
+        let scene = test_data::scene(13, 9, 4);
+        let reported = Mutex::new(vec![]);
+        let callback = |done: u64, total: u64| reported.lock().unwrap().push((done, total));
+        let options = RenderOptions {
+            threads: 2,
+            tile_size: 4,
+            ..Default::default()
+        };
+        render_with_stats(&scene, &options, &callback);
+
+        let mut reported = reported.into_inner().unwrap();
+        reported.sort();
+        // One event per tile, ending with all 4 samples of every pixel.
+        assert_eq!(reported.len(), 4 * 3);
+        assert_eq!(reported.last(), Some(&(13 * 9 * 4, 13 * 9 * 4)));
+    }
 }
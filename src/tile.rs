@@ -0,0 +1,167 @@
+use std::{f64::consts::PI, str::FromStr};
+
+use crate::error::Error;
+
+/// A rectangle of pixels rendered as one unit of work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// The order in which tiles are handed out to workers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Left to right, top to bottom.
+    Scanline,
+    /// Outwards from the center of the image.
+    #[default]
+    Spiral,
+    /// Along a Hilbert curve, keeping consecutive tiles close together.
+    Hilbert,
+}
+
+impl FromStr for TileOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "scanline" => Ok(Self::Scanline),
+            "spiral" => Ok(Self::Spiral),
+            "hilbert" => Ok(Self::Hilbert),
+            _ => Err(Error::UnknownTileOrder(s.to_string())),
+        }
+    }
+}
+
+// The distance along a Hilbert curve filling an `n` by `n` grid, `n` being a
+// power of two, of the cell `(x, y)`.
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s) > 0;
+        let ry = (y & s) > 0;
+        d += s * s * ((3 * rx as usize) ^ ry as usize);
+        if !ry {
+            if rx {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+impl Tile {
+    /// Splits a `width` by `height` image into tiles of at most `size` pixels
+    /// square, sorted in the given order.
+    pub fn split(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+        let size = size.max(1);
+        let cols = width.div_ceil(size);
+        let rows = height.div_ceil(size);
+
+        let mut cells: Vec<(usize, usize)> = (0..rows)
+            .flat_map(|ty| (0..cols).map(move |tx| (tx, ty)))
+            .collect();
+
+        match order {
+            TileOrder::Scanline => {}
+            TileOrder::Spiral => {
+                let key = |&(tx, ty): &(usize, usize)| {
+                    let dx = tx as f64 + 0.5 - cols as f64 / 2.0;
+                    let dy = ty as f64 + 0.5 - rows as f64 / 2.0;
+                    let ring = dx.abs().max(dy.abs());
+                    let angle = dy.atan2(dx).rem_euclid(2.0 * PI);
+                    (ring, angle)
+                };
+                cells.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+            }
+            TileOrder::Hilbert => {
+                let n = cols.max(rows).next_power_of_two();
+                cells.sort_by_key(|&(tx, ty)| hilbert_index(n, tx, ty));
+            }
+        }
+
+        cells
+            .into_iter()
+            .map(|(tx, ty)| {
+                let x = tx * size;
+                let y = ty * size;
+                Tile {
+                    x,
+                    y,
+                    width: size.min(width - x),
+                    height: size.min(height - y),
+                }
+            })
+            .collect()
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// The pixels of the tile in row-major order.
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y..self.y + self.height)
+            .flat_map(move |j| (self.x..self.x + self.width).map(move |i| (i, j)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_covers(tiles: &[Tile], width: usize, height: usize) {
+        let mut seen = vec![0; width * height];
+        for tile in tiles {
+            for (i, j) in tile.pixels() {
+                seen[j * width + i] += 1;
+            }
+        }
+        assert!(seen.iter().all(|&n| n == 1));
+    }
+
+    #[test]
+    fn test_split_covers_image() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let tiles = Tile::split(37, 21, 8, order);
+            assert_eq!(tiles.len(), 5 * 3);
+            assert_covers(&tiles, 37, 21);
+        }
+    }
+
+    #[test]
+    fn test_scanline() {
+        let tiles = Tile::split(4, 4, 2, TileOrder::Scanline);
+        let origins: Vec<_> = tiles.iter().map(|t| (t.x, t.y)).collect();
+        assert_eq!(origins, vec![(0, 0), (2, 0), (0, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn test_spiral_starts_in_center() {
+        let tiles = Tile::split(30, 30, 10, TileOrder::Spiral);
+        assert_eq!((tiles[0].x, tiles[0].y), (10, 10));
+    }
+
+    #[test]
+    fn test_hilbert_steps_to_neighbours() {
+        let tiles = Tile::split(64, 64, 8, TileOrder::Hilbert);
+        for pair in tiles.windows(2) {
+            let dx = pair[0].x.abs_diff(pair[1].x);
+            let dy = pair[0].y.abs_diff(pair[1].y);
+            assert_eq!(dx + dy, 8);
+        }
+    }
+
+    #[test]
+    fn test_parse_order() {
+        assert_eq!("Hilbert".parse::<TileOrder>().unwrap(), TileOrder::Hilbert);
+        assert!("zigzag".parse::<TileOrder>().is_err());
+    }
+}
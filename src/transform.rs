@@ -4,125 +4,198 @@ use crate::{
     aabb::AABB,
     interval::Interval,
     math::{Point3, Vec3},
+    matrix::Mat4,
     object::{Hit, Object},
     point,
     ray::Ray,
-    vec3,
+    rng::Rng,
 };
 
-pub struct Translate {
+/// An object placed in the world by an affine transform.
+pub struct Transform {
     pub obj: Box<dyn Object>,
-    pub offset: Vec3,
+    pub matrix: Mat4,
+    inverse: Mat4,
+    normal_matrix: Mat4,
     bbox: AABB,
 }
 
-impl Translate {
-    pub fn new(obj: Box<dyn Object>, offset: Vec3) -> Self {
-        let bbox = obj.bbox() + offset;
-        Self { obj, offset, bbox }
+fn transform_bbox(bbox: &AABB, matrix: &Mat4) -> AABB {
+    let mut min = point!(INFINITY, INFINITY, INFINITY);
+    let mut max = point!(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY);
+
+    for i in 0..2 {
+        for j in 0..2 {
+            for k in 0..2 {
+                let x = if i == 1 { bbox.x.max } else { bbox.x.min };
+                let y = if j == 1 { bbox.y.max } else { bbox.y.min };
+                let z = if k == 1 { bbox.z.max } else { bbox.z.min };
+
+                let tester = matrix.transform_point(&point!(x, y, z));
+
+                for c in 0..3 {
+                    min[c] = min[c].min(tester[c]);
+                    max[c] = max[c].max(tester[c]);
+                }
+            }
+        }
     }
+
+    AABB::from_points(min, max)
 }
 
-impl Object for Translate {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<Hit> {
-        let offset_r = Ray::new(r.origin - self.offset, r.direction, r.time);
+impl Transform {
+    /// Places `obj` with `matrix`, or returns `None` if the matrix can't be
+    /// inverted.
+    pub fn new(obj: Box<dyn Object>, matrix: Mat4) -> Option<Self> {
+        let inverse = matrix.inverse()?;
+        Some(Self::with_inverse(obj, matrix, inverse))
+    }
 
-        self.obj.hit(&offset_r, ray_t).map(|mut hit| {
-            hit.p += self.offset;
-            hit
-        })
+    fn with_inverse(obj: Box<dyn Object>, matrix: Mat4, inverse: Mat4) -> Self {
+        let bbox = transform_bbox(obj.bbox(), &matrix);
+        Self {
+            obj,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            bbox,
+        }
     }
 
-    fn bbox(&self) -> &AABB {
-        &self.bbox
+    pub fn translate(obj: Box<dyn Object>, offset: Vec3) -> Self {
+        Self::with_inverse(obj, Mat4::translate(&offset), Mat4::translate(&-offset))
     }
-}
 
-pub struct RotateY {
-    pub obj: Box<dyn Object>,
-    sin_theta: f64,
-    cos_theta: f64,
-    bbox: AABB,
-}
+    pub fn rotate(obj: Box<dyn Object>, axis: Vec3, angle: f64) -> Self {
+        Self::with_inverse(obj, Mat4::rotate(&axis, angle), Mat4::rotate(&axis, -angle))
+    }
 
-impl RotateY {
-    pub fn new(obj: Box<dyn Object>, angle: f64) -> Self {
-        let angle = angle.to_radians();
-        let sin_theta = angle.sin();
-        let cos_theta = angle.cos();
-        let bbox = obj.bbox();
+    pub fn rotate_x(obj: Box<dyn Object>, angle: f64) -> Self {
+        Self::rotate(obj, Vec3::new(1.0, 0.0, 0.0), angle)
+    }
 
-        let mut min = point!(INFINITY, INFINITY, INFINITY);
-        let mut max = point!(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY);
+    pub fn rotate_y(obj: Box<dyn Object>, angle: f64) -> Self {
+        Self::rotate(obj, Vec3::new(0.0, 1.0, 0.0), angle)
+    }
 
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
-                    let x = (i as f64) * bbox.x.max + ((1 - i) as f64) * bbox.x.min;
-                    let y = (j as f64) * bbox.y.max + ((1 - j) as f64) * bbox.y.min;
-                    let z = (k as f64) * bbox.z.max + ((1 - k) as f64) * bbox.z.min;
+    pub fn rotate_z(obj: Box<dyn Object>, angle: f64) -> Self {
+        Self::rotate(obj, Vec3::new(0.0, 0.0, 1.0), angle)
+    }
+}
 
-                    let newx = cos_theta * x + sin_theta * z;
-                    let newz = -sin_theta * x + cos_theta * z;
+impl Object for Transform {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rng: &mut Rng) -> Option<Hit> {
+        // Transform the ray from world space to object space. The direction is
+        // left unnormalized so that hit distances carry over unchanged.
+        let local_r = Ray::new(
+            self.inverse.transform_point(&r.origin),
+            self.inverse.transform_vector(&r.direction),
+            r.time,
+        );
 
-                    let tester = vec3!(newx, y, newz);
+        self.obj.hit(&local_r, ray_t, rng).map(|mut hit| {
+            // Transform the intersection from object space back to world space.
+            hit.p = self.matrix.transform_point(&hit.p);
+            hit.normal = self.normal_matrix.transform_vector(&hit.normal).unit();
+            hit
+        })
+    }
 
-                    for c in 0..3 {
-                        min[c] = min[c].min(tester[c]);
-                        max[c] = max[c].max(tester[c]);
-                    }
-                }
-            }
-        }
+    fn bbox(&self) -> &AABB {
+        &self.bbox
+    }
 
-        Self {
-            obj,
-            sin_theta,
-            cos_theta,
-            bbox: AABB::from_points(min, max),
-        }
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64, rng: &mut Rng) -> f64 {
+        // Densities over directions change with the solid angle the transform
+        // maps a world direction to.
+        let local_direction = self.inverse.transform_vector(&direction.unit());
+        let pdf = self.obj.pdf_value(
+            &self.inverse.transform_point(origin),
+            &local_direction,
+            time,
+            rng,
+        );
+        let stretch = local_direction.length();
+        pdf * self.inverse.determinant3().abs() / (stretch * stretch * stretch)
+    }
+
+    fn random(&self, origin: &Point3, time: f64, rng: &mut Rng) -> Vec3 {
+        let local_origin = self.inverse.transform_point(origin);
+        self.matrix
+            .transform_vector(&self.obj.random(&local_origin, time, rng))
+    }
+
+    fn collect_lights(&self, lights: &mut Vec<Box<dyn Object>>) {
+        let mut inner = vec![];
+        self.obj.collect_lights(&mut inner);
+        lights.extend(inner.into_iter().map(|light| {
+            Box::new(Self::with_inverse(light, self.matrix, self.inverse)) as Box<dyn Object>
+        }));
     }
 }
 
-impl Object for RotateY {
-    fn hit(&self, r: &Ray, ray_t: &Interval) -> Option<Hit> {
-        // Transform the ray from world space to object space.
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
 
-        let origin = point!(
-            (self.cos_theta * r.origin.x()) - (self.sin_theta * r.origin.z()),
-            r.origin.y(),
-            (self.sin_theta * r.origin.x()) + (self.cos_theta * r.origin.z())
-        );
+    use rand::SeedableRng;
 
-        let direction = vec3!(
-            (self.cos_theta * r.direction.x()) - (self.sin_theta * r.direction.z()),
-            r.direction.y(),
-            (self.sin_theta * r.direction.x()) + (self.cos_theta * r.direction.z())
-        );
+    use crate::{
+        assert_in_delta, color::Color, diffuse_light::DiffuseLight, material::Material, quad::Quad,
+        rgb, vec3,
+    };
 
-        let rotated_r = Ray::new(origin, direction, r.time);
+    use super::*;
 
-        // Determine whether an intersection exists in object space (and if so, where).
+    fn unit_quad() -> Box<dyn Object> {
+        let mat: Arc<dyn Material> = Arc::new(DiffuseLight::solid(rgb!(1.0)));
+        Box::new(Quad::new(
+            point!(-0.5, 0.0, -0.5),
+            vec3!(1.0, 0.0, 0.0),
+            vec3!(0.0, 0.0, 1.0),
+            &mat,
+        ))
+    }
 
-        self.obj.hit(&rotated_r, ray_t).map(|mut hit| {
-            // Transform the intersection from object space back to world space.
-            hit.p = point!(
-                (self.cos_theta * hit.p.x()) + (self.sin_theta * hit.p.z()),
-                hit.p.y(),
-                (-self.sin_theta * hit.p.x()) + (self.cos_theta * hit.p.z())
-            );
-
-            hit.normal = vec3!(
-                (self.cos_theta * hit.normal.x()) + (self.sin_theta * hit.normal.z()),
-                hit.normal.y(),
-                (-self.sin_theta * hit.normal.x()) + (self.cos_theta * hit.normal.z())
-            );
+    #[test]
+    fn test_hit() {
+        let m = Mat4::translate(&vec3!(0.0, 2.0, 0.0)) * Mat4::rotate_x(90.0);
+        let t = Transform::new(unit_quad(), m).unwrap();
+        let r = Ray::new(point!(0.1, 2.1, 5.0), vec3!(0.0, 0.0, -1.0), 0.0);
+        let hit = t
+            .hit(&r, &Interval::from(0.001), &mut Rng::seed_from_u64(0))
+            .unwrap();
+        assert_in_delta!(hit.t, 5.0);
+        assert_in_delta!(hit.p.z(), 0.0);
+        assert_in_delta!(hit.normal.z(), 1.0);
+        assert_in_delta!(t.bbox().y.min, 1.5, 1e-3);
+    }
 
-            hit
-        })
+    #[test]
+    fn test_scaled_normal() {
+        let m = Mat4::scale(&vec3!(4.0, 1.0, 1.0)) * Mat4::rotate_z(45.0);
+        let t = Transform::new(unit_quad(), m).unwrap();
+        let r = Ray::new(point!(0.0, 5.0, 0.0), vec3!(0.0, -1.0, 0.0), 0.0);
+        let hit = t
+            .hit(&r, &Interval::from(0.001), &mut Rng::seed_from_u64(0))
+            .unwrap();
+        assert_in_delta!(hit.normal.length(), 1.0);
+        // The quad's surface direction (1, 1, 0) becomes (4, 1, 0).
+        assert_in_delta!(hit.normal.dot(&vec3!(4.0, 1.0, 0.0)), 0.0);
     }
 
-    fn bbox(&self) -> &AABB {
-        &self.bbox
+    #[test]
+    fn test_pdf_matches_area() {
+        // A quad scaled to 2x2 seen from 10 units away straight on.
+        let t = Transform::new(unit_quad(), Mat4::scale(&vec3!(2.0, 1.0, 2.0))).unwrap();
+        let origin = point!(0.0, 10.0, 0.0);
+        let pdf = t.pdf_value(
+            &origin,
+            &vec3!(0.0, -1.0, 0.0),
+            0.0,
+            &mut Rng::seed_from_u64(0),
+        );
+        assert_in_delta!(pdf, 100.0 / 4.0, 1e-9);
     }
 }
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    interval::Interval,
+    material::Material,
+    math::{Point3, Vec2, Vec3},
+    object::{area_to_solid_angle, Hit, Object},
+    ray::Ray,
+    rng::Rng,
+    util::EPSILON,
+};
+
+// Möller–Trumbore ray-triangle intersection. Returns the ray parameter and the
+// barycentric coordinates of the hit relative to the edges `e1` and `e2`.
+pub fn intersect(
+    a: &Point3,
+    e1: &Vec3,
+    e2: &Vec3,
+    r: &Ray,
+    ray_t: &Interval,
+) -> Option<(f64, Vec2)> {
+    let pvec = r.direction.cross(e2);
+    let det = e1.dot(&pvec);
+
+    if det.abs() < EPSILON {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let tvec = r.origin - *a;
+    let u = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let qvec = tvec.cross(e1);
+    let v = r.direction.dot(&qvec) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = e2.dot(&qvec) * inv_det;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+
+    Some((t, Vec2::new(u, v)))
+}
+
+// Uniformly samples a point on the triangle.
+pub fn sample_point(rng: &mut Rng, a: &Point3, e1: &Vec3, e2: &Vec3) -> Point3 {
+    let (s, t) = rng.get_2d();
+    let s = s.sqrt();
+    *a + (s * (1.0 - t)) * *e1 + (s * t) * *e2
+}
+
+pub fn triangle_bbox(a: Point3, b: Point3, c: Point3) -> AABB {
+    AABB::from_points(a, b) + AABB::from_points(a, c)
+}
+
+#[derive(Clone)]
+pub struct Triangle {
+    pub a: Point3,
+    pub b: Point3,
+    pub c: Point3,
+    pub mat: Arc<dyn Material>,
+    bbox: AABB,
+    e1: Vec3,
+    e2: Vec3,
+    normal: Vec3,
+    area: f64,
+}
+
+impl Triangle {
+    pub fn new(a: Point3, b: Point3, c: Point3, mat: &Arc<dyn Material>) -> Self {
+        let e1 = b - a;
+        let e2 = c - a;
+        let n = e1.cross(&e2);
+        Self {
+            a,
+            b,
+            c,
+            mat: Arc::clone(mat),
+            bbox: triangle_bbox(a, b, c),
+            e1,
+            e2,
+            normal: n.unit(),
+            area: 0.5 * n.length(),
+        }
+    }
+}
+
+impl Object for Triangle {
+    fn hit(&self, r: &Ray, ray_t: &Interval, _rng: &mut Rng) -> Option<Hit> {
+        intersect(&self.a, &self.e1, &self.e2, r, ray_t)
+            .map(|(t, uv)| Hit::new(t, r.at(t), r, self.normal, uv, &self.mat))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64, rng: &mut Rng) -> f64 {
+        match self.hit(
+            &Ray::new(*origin, *direction, time),
+            &Interval::from(0.001),
+            rng,
+        ) {
+            Some(hit) => area_to_solid_angle(hit.t, direction, &self.normal, self.area),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, _time: f64, rng: &mut Rng) -> Vec3 {
+        sample_point(rng, &self.a, &self.e1, &self.e2) - *origin
+    }
+
+    fn collect_lights(&self, lights: &mut Vec<Box<dyn Object>>) {
+        if self.mat.is_emissive() {
+            lights.push(Box::new(self.clone()));
+        }
+    }
+
+    fn bbox(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use crate::{assert_in_delta, color::Color, lambertian::Lambertian, point, rgb, vec3};
+
+    use super::*;
+
+    fn rng() -> Rng {
+        Rng::seed_from_u64(0)
+    }
+
+    fn triangle() -> Triangle {
+        let mat: Arc<dyn Material> = Arc::new(Lambertian::solid(rgb!(0.5)));
+        Triangle::new(
+            point!(0.0, 0.0, 0.0),
+            point!(1.0, 0.0, 0.0),
+            point!(0.0, 1.0, 0.0),
+            &mat,
+        )
+    }
+
+    #[test]
+    fn test_hit() {
+        let r = Ray::new(point!(0.25, 0.5, 1.0), vec3!(0.0, 0.0, -1.0), 0.0);
+        let hit = triangle()
+            .hit(&r, &Interval::from(0.001), &mut rng())
+            .unwrap();
+        assert_in_delta!(hit.t, 1.0);
+        assert_eq!(hit.p, point!(0.25, 0.5, 0.0));
+        assert_eq!(hit.normal, vec3!(0.0, 0.0, 1.0));
+        assert!(hit.front_face);
+        assert_in_delta!(hit.uv.u(), 0.25);
+        assert_in_delta!(hit.uv.v(), 0.5);
+    }
+
+    #[test]
+    fn test_hit_back_face() {
+        let r = Ray::new(point!(0.25, 0.25, -1.0), vec3!(0.0, 0.0, 1.0), 0.0);
+        let hit = triangle()
+            .hit(&r, &Interval::from(0.001), &mut rng())
+            .unwrap();
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal, vec3!(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_miss() {
+        let t = triangle();
+        let outside = Ray::new(point!(0.75, 0.75, 1.0), vec3!(0.0, 0.0, -1.0), 0.0);
+        assert!(t
+            .hit(&outside, &Interval::from(0.001), &mut rng())
+            .is_none());
+
+        let parallel = Ray::new(point!(0.25, 0.25, 1.0), vec3!(1.0, 0.0, 0.0), 0.0);
+        assert!(t
+            .hit(&parallel, &Interval::from(0.001), &mut rng())
+            .is_none());
+
+        let behind = Ray::new(point!(0.25, 0.25, 1.0), vec3!(0.0, 0.0, 1.0), 0.0);
+        assert!(t.hit(&behind, &Interval::from(0.001), &mut rng()).is_none());
+    }
+}
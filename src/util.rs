@@ -1,17 +1,18 @@
 use std::f64::consts::PI;
 
-use rand::random;
+use rand::Rng as _;
 
 use crate::{
-    math::{Point3, Vec2},
+    math::{Point3, Vec2, Vec3},
+    rng::Rng,
     vec2,
 };
 
 pub const EPSILON: f64 = 1e-8;
 
-pub fn random_in_range(min: f64, max: f64) -> f64 {
+pub fn random_in_range(rng: &mut Rng, min: f64, max: f64) -> f64 {
     // Returns a random real in [min,max).
-    min + (max - min) * random::<f64>()
+    min + (max - min) * rng.random::<f64>()
 }
 
 pub fn linear_to_gamma(linear_component: f64) -> f64 {
@@ -28,6 +29,26 @@ pub fn gamma_to_linear(gamma_component: f64, gamma: f64) -> f64 {
     0.0
 }
 
+/// The 64-bit FNV-1a hash of `bytes`.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
 pub fn sphere_uv(p: &Point3) -> Vec2 {
     // p: a given point on the sphere of radius one, centered at the origin.
     // u: returned value [0,1] of angle around the Y axis from X=-1.
@@ -42,15 +63,28 @@ pub fn sphere_uv(p: &Point3) -> Vec2 {
     vec2!(phi / (2.0 * PI), theta / PI)
 }
 
+/// The unit vector [`sphere_uv`] maps to `uv`.
+pub fn sphere_direction(uv: &Vec2) -> Vec3 {
+    let theta = uv.v() * PI;
+    let phi = uv.u() * 2.0 * PI;
+    Vec3::new(
+        -theta.sin() * phi.cos(),
+        -theta.cos(),
+        theta.sin() * phi.sin(),
+    )
+}
+
 #[cfg(test)]
 mod test {
+    use rand::SeedableRng;
+
     use crate::assert_in_delta;
 
     use super::*;
 
     #[test]
     fn test_random_in_range() {
-        let x = random_in_range(-10.0, 10.0);
+        let x = random_in_range(&mut Rng::seed_from_u64(0), -10.0, 10.0);
         assert!(-10.0 < x && x < 10.0);
     }
 
@@ -61,6 +95,30 @@ mod test {
         assert_in_delta!(linear_to_gamma(0.25), 0.5);
     }
 
+    #[test]
+    fn test_power_heuristic() {
+        assert_eq!(power_heuristic(1.0, 0.0), 1.0);
+        assert_eq!(power_heuristic(0.0, 1.0), 0.0);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+        assert_in_delta!(power_heuristic(1.0, 2.0), 0.2);
+        assert_in_delta!(power_heuristic(1.0, 2.0) + power_heuristic(2.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn test_sphere_direction() {
+        for p in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.6, 0.0, -0.8),
+            Vec3::new(0.48, 0.6, 0.64),
+        ] {
+            let q = sphere_direction(&sphere_uv(&p));
+            assert_in_delta!((q - p).length(), 0.0);
+        }
+        assert_in_delta!(sphere_direction(&vec2!(0.3, 1.0)).y(), 1.0);
+    }
+
     #[test]
     fn test_gamma_to_linear() {
         assert_eq!(gamma_to_linear(0.0, 2.2), 0.0);